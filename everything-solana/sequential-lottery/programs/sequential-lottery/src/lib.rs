@@ -1,58 +1,194 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::{pubkey::Pubkey};
-use std::collections::BTreeMap; // Add this import
+use anchor_lang::solana_program::{hash::{hash, hashv}, pubkey::Pubkey};
+use match_rules::{matches_revealed, sequence_key, Rule};
+// Deprecated in favour of solana-instructions-sysvar, but saves a direct dependency
+#[allow(deprecated)]
+use anchor_lang::solana_program::sysvar::instructions as instructions_sysvar;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_lang::system_program;
 
 declare_id!("3UJcW6qB7LJJGdJbRqDh4SkWDVGCF15YzkuRtf5nvRJJ");
 
-const MINT_PRICE: u64 = 10_000_000; // 0.01 SOL in lamports
-const TRANSACTION_FEE: u64 = 250; // 2.5% in basis points
-const OWNER_FEE: u64 = 100; // 1% in basis points
-const MARKETPLACE_FEE: u64 = 200; // 2% in basis points
-const REVEAL_INTERVAL: i64 = 2 * 24 * 60 * 60; // 2 days in seconds
+// Starting values for Config; update_config changes them afterwards
+const DEFAULT_MINT_PRICE: u64 = 10_000_000; // 0.01 SOL in lamports
+const DEFAULT_OWNER_FEE_BPS: u16 = 100; // 1%
+const DEFAULT_MARKETPLACE_FEE_BPS: u16 = 200; // 2%
+const DEFAULT_REVEAL_INTERVAL: i64 = 2 * 24 * 60 * 60; // 2 days in seconds
+
+// Bounds update_config enforces
+const MAX_TOTAL_FEE_BPS: u16 = 1000; // Owner and marketplace fees together, 10%
+const MIN_REVEAL_INTERVAL: i64 = 60 * 60; // 1 hour
+const MAX_REVEAL_INTERVAL: i64 = 30 * 24 * 60 * 60; // 30 days
+
+const SETTLEMENT_PERIOD: i64 = 24 * 60 * 60; // 1 day for winners to register
+const CANCEL_TIMEOUT_INTERVALS: i64 = 3; // Reveal intervals before anyone can cancel a stalled round
+
+// Bits of Config::paused, one per group of operations that can be halted
+pub const PAUSE_MINT: u8 = 1 << 0;
+pub const PAUSE_REVEAL: u8 = 1 << 1;
+pub const PAUSE_CLAIM: u8 = 1 << 2;
+pub const PAUSE_MARKETPLACE: u8 = 1 << 3;
+
+pub const MAX_APPROVERS: usize = 8; // Approvals are tracked as a u8 bitmask
+
+/// Seed of the PDA an oracle program signs `fulfill_reveal` with.
+pub const ORACLE_AUTHORITY_SEED: &[u8] = b"oracle";
 
 #[program]
 pub mod sequential_lottery {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+    pub fn initialize(ctx: Context<Initialize>, params: InitializeParams) -> Result<()> {
+        let InitializeParams {
+            prize_tiers,
+            sales_close_index,
+            price_multipliers,
+            min_number,
+            max_number,
+            allow_repeats,
+            match_mode,
+        } = params;
+
+        require!(valid_prize_tiers(&prize_tiers), ErrorCode::InvalidPrizeTiers);
+        require!(
+            sales_close_index > 0 && sales_close_index <= 6,
+            ErrorCode::InvalidSalesCloseIndex
+        );
+        require!(
+            valid_price_multipliers(&price_multipliers, sales_close_index),
+            ErrorCode::InvalidPriceMultipliers
+        );
+        // Without repeats the range has to hold six distinct numbers
+        require!(
+            min_number <= max_number && (allow_repeats || max_number - min_number >= 5),
+            ErrorCode::InvalidNumberBounds
+        );
+
         let lottery = &mut ctx.accounts.lottery;
         lottery.current_round = 0;
         lottery.current_reveal_index = 0;
         lottery.last_reveal_time = Clock::get()?.unix_timestamp;
         lottery.accumulated_prize = 0;
+        lottery.outstanding_liabilities = 0;
+        lottery.round_ticket_sales = 0;
+        lottery.protocol_fees_accrued = 0;
+        lottery.round_mint_fees = 0;
+        lottery.marketplace_fees_accrued = 0;
         lottery.round_complete = false;
+        lottery.prize_tiers = prize_tiers;
+        lottery.round_settled = false;
+        lottery.round_cancelled = false;
+        lottery.sales_close_index = sales_close_index;
+        lottery.price_multipliers = price_multipliers;
+        lottery.min_number = min_number;
+        lottery.max_number = max_number;
+        lottery.allow_repeats = allow_repeats;
+        lottery.match_mode = match_mode;
+        lottery.next_ticket_id = 0;
         lottery.bump = ctx.bumps.lottery; // Simple direct access
+
+        open_round_result(&mut ctx.accounts.round_result, lottery, ctx.bumps.round_result);
+
+        // The deployer holds every role until it hands them out
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.user.key();
+        config.pending_admin = Pubkey::default();
+        config.revealer = ctx.accounts.user.key();
+        config.fee_collector = ctx.accounts.user.key();
+        config.paused = 0;
+        config.approvers = [Pubkey::default(); MAX_APPROVERS];
+        config.approver_count = 0;
+        config.threshold = 0;
+        config.approvers_epoch = 0;
+        config.proposal_count = 0;
+        config.mint_price = DEFAULT_MINT_PRICE;
+        config.owner_fee_bps = DEFAULT_OWNER_FEE_BPS;
+        config.marketplace_fee_bps = DEFAULT_MARKETPLACE_FEE_BPS;
+        config.reveal_interval = DEFAULT_REVEAL_INTERVAL;
+        config.oracle_program = Pubkey::default();
+        config.oracle_signer = Pubkey::default();
+        config.bump = ctx.bumps.config;
         Ok(())
     }
 
     pub fn mint_ticket(ctx: Context<MintTicket>, numbers: [u8; 6]) -> Result<()> {
+        require!(ctx.accounts.config.paused & PAUSE_MINT == 0, ErrorCode::Paused);
+        let lottery = &mut ctx.accounts.lottery;
         for num in numbers.iter() {
-            require!(*num <= 99, ErrorCode::InvalidNumberRange);
+            require!(
+                *num >= lottery.min_number && *num <= lottery.max_number,
+                ErrorCode::InvalidNumberRange
+            );
         }
+        require!(
+            lottery.allow_repeats || !has_repeats(&numbers),
+            ErrorCode::RepeatedNumber
+        );
+        require!(!lottery.round_cancelled, ErrorCode::RoundCancelled);
+        require!(
+            lottery.current_reveal_index < lottery.sales_close_index,
+            ErrorCode::SalesClosed
+        );
 
-        let lottery = &mut ctx.accounts.lottery;
-        
-        let existing_ticket = lottery.tickets.iter().find(|t| t.numbers == numbers);
-        require!(existing_ticket.is_none(), ErrorCode::DuplicateSequence);
-
-        let owner_fee = MINT_PRICE.checked_mul(OWNER_FEE).unwrap() / 10000;
-        let prize_contribution = MINT_PRICE.checked_sub(owner_fee).unwrap();
+        // Later tickets pay more since part of the sequence is already public
+        let config = &ctx.accounts.config;
+        let price = config
+            .mint_price
+            .checked_mul(lottery.price_multipliers[lottery.current_reveal_index as usize] as u64)
+            .unwrap()
+            / 10000;
+        let owner_fee = price.checked_mul(config.owner_fee_bps as u64).unwrap() / 10000;
+        let prize_contribution = price.checked_sub(owner_fee).unwrap();
 
         lottery.accumulated_prize = lottery.accumulated_prize.checked_add(prize_contribution).unwrap();
+        lottery.protocol_fees_accrued = lottery.protocol_fees_accrued.checked_add(owner_fee).unwrap();
+        lottery.round_mint_fees = lottery.round_mint_fees.checked_add(owner_fee).unwrap();
+        lottery.round_ticket_sales = lottery.round_ticket_sales.checked_add(price).unwrap();
+
+        // Collect the mint price so the pool (and any refund) is actually funded
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.lottery.to_account_info(),
+                },
+            ),
+            price,
+        )?;
 
-        let ticket = Ticket {
-            numbers,
-            claimed: false,
-            owner: *ctx.accounts.user.key,
-            mint_time: Clock::get()?.unix_timestamp,
-        };
+        let lottery = &mut ctx.accounts.lottery;
+        emit!(FeesAccrued {
+            protocol_fee: owner_fee,
+            marketplace_fee: 0,
+        });
 
-        lottery.tickets.push(ticket);
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.lottery = lottery.key();
+        ticket.round = lottery.current_round;
+        ticket.ticket_id = lottery.next_ticket_id;
+        ticket.numbers = numbers;
+        ticket.claimed = false;
+        ticket.registered = false;
+        ticket.refunded = false;
+        ticket.listed = false;
+        ticket.prize_contribution = prize_contribution;
+        ticket.owner_fee = owner_fee;
+        ticket.owner = *ctx.accounts.user.key;
+        ticket.mint_time = Clock::get()?.unix_timestamp;
+        ticket.bump = ctx.bumps.ticket;
+
+        // Creating this account is what rejects a repeated sequence
+        ctx.accounts.sequence_claim.ticket = ticket.key();
+
+        lottery.next_ticket_id += 1;
 
         emit!(TicketMinted {
-            ticket_id: lottery.tickets.len() as u64 - 1,
+            ticket_id: ticket.ticket_id,
             owner: *ctx.accounts.user.key,
             numbers,
+            price,
+            reveal_index: lottery.current_reveal_index,
         });
 
         Ok(())
@@ -60,61 +196,249 @@ pub mod sequential_lottery {
 
     // Change the reveal_number function to:
     pub fn reveal_number(ctx: Context<RevealNumber>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        require!(config.paused & PAUSE_REVEAL == 0, ErrorCode::Paused);
+        authorize(
+            config,
+            config.revealer,
+            ctx.accounts.revealer.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::RevealNumber {
+                round: ctx.accounts.lottery.current_round,
+                reveal_index: ctx.accounts.lottery.current_reveal_index,
+            },
+        )?;
+
+        // With an oracle configured, reveals only go through request/fulfill
+        require!(
+            config.oracle_program == Pubkey::default(),
+            ErrorCode::WrongRandomnessSource
+        );
+
         let lottery = &mut ctx.accounts.lottery;
-        require!(!lottery.round_complete, ErrorCode::RoundComplete);
+        check_reveal_ready(lottery, &ctx.accounts.config)?;
+
+        // SlotHashes is too big to deserialize on-chain, so the newest hash is
+        // read in place: after the entry count come (slot, hash) pairs
+        let slot_hashes = ctx.accounts.slot_hashes.try_borrow_data()?;
+        let recent_hash = slot_hashes.get(16..48).ok_or(ProgramError::UnsupportedSysvar)?;
+        let number = draw_number(lottery, entropy_from(recent_hash));
+
+        record_reveal(lottery, &mut ctx.accounts.round_result, number)
+    }
+
+    // Points reveals at an oracle program whose authority PDA must sign
+    // fulfill_reveal, with `oracle_signer`'s ed25519 signature as the proof.
+    // The default key for both switches back to slot hash reveals.
+    pub fn configure_oracle(
+        ctx: Context<ConfigureOracle>,
+        oracle_program: Pubkey,
+        oracle_signer: Pubkey,
+    ) -> Result<()> {
+        let config = &ctx.accounts.config;
+        authorize(
+            config,
+            config.admin,
+            ctx.accounts.admin.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::ConfigureOracle {
+                oracle_program,
+                oracle_signer,
+            },
+        )?;
+
+        // The randomness source can't change once players have bought in
+        let lottery = &ctx.accounts.lottery;
         require!(
-            Clock::get()?.unix_timestamp >= lottery.last_reveal_time + REVEAL_INTERVAL,
-            ErrorCode::TooEarlyForReveal
+            lottery.next_ticket_id == 0 && lottery.current_reveal_index == 0,
+            ErrorCode::RoundAlreadyStarted
         );
 
-        let slot_hashes_account = ctx.accounts.slot_hashes.to_account_info();
-        let _slot_hashes = SlotHashes::from_account_info(&slot_hashes_account)?;
-        let recent_slot = _slot_hashes.slot_hashes().first().unwrap().0;
-        let number = (recent_slot % 100) as u8;
+        let config = &mut ctx.accounts.config;
+        config.oracle_program = oracle_program;
+        config.oracle_signer = oracle_signer;
 
-        // Store current_reveal_index in local variable to avoid borrow conflict
-        let reveal_index = lottery.current_reveal_index;
-        lottery.revealed_numbers[reveal_index as usize] = number;
+        emit!(OracleConfigured {
+            oracle_program,
+            oracle_signer,
+        });
 
-        emit!(NumberRevealed {
-            round: lottery.current_round,
-            number,
-            reveal_index,
+        Ok(())
+    }
+
+    pub fn request_reveal(ctx: Context<RequestReveal>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        require!(config.paused & PAUSE_REVEAL == 0, ErrorCode::Paused);
+        require!(
+            config.oracle_program != Pubkey::default(),
+            ErrorCode::WrongRandomnessSource
+        );
+        authorize(
+            config,
+            config.revealer,
+            ctx.accounts.revealer.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::RevealNumber {
+                round: ctx.accounts.lottery.current_round,
+                reveal_index: ctx.accounts.lottery.current_reveal_index,
+            },
+        )?;
+
+        let lottery = &ctx.accounts.lottery;
+        check_reveal_ready(lottery, config)?;
+
+        // The oracle signs this seed; its signature is the proof and its hash
+        // the randomness
+        let clock = Clock::get()?;
+        let request = &mut ctx.accounts.reveal_request;
+        request.lottery = lottery.key();
+        request.round = lottery.current_round;
+        request.reveal_index = lottery.current_reveal_index;
+        request.seed = hashv(&[
+            lottery.key().as_ref(),
+            &lottery.current_round.to_le_bytes(),
+            &[lottery.current_reveal_index],
+            &clock.slot.to_le_bytes(),
+        ])
+        .to_bytes();
+        request.randomness = [0; 32];
+        request.requested_at = clock.unix_timestamp;
+        request.fulfilled = false;
+        request.bump = ctx.bumps.reveal_request;
+
+        emit!(RevealRequested {
+            round: request.round,
+            reveal_index: request.reveal_index,
+            seed: request.seed,
         });
 
-        lottery.current_reveal_index += 1;
-        lottery.last_reveal_time = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
 
-        if lottery.current_reveal_index == 6 {
-            lottery.round_complete = true;
-        }
+    pub fn fulfill_reveal(ctx: Context<FulfillReveal>, proof: [u8; 64]) -> Result<()> {
+        let config = &ctx.accounts.config;
+        require!(config.paused & PAUSE_REVEAL == 0, ErrorCode::Paused);
+        require!(
+            config.oracle_program != Pubkey::default(),
+            ErrorCode::WrongRandomnessSource
+        );
+
+        let request = &mut ctx.accounts.reveal_request;
+        require!(!request.fulfilled, ErrorCode::RequestAlreadyFulfilled);
+        let lottery = &mut ctx.accounts.lottery;
+        require!(!lottery.round_complete, ErrorCode::RoundComplete);
+        require!(!lottery.round_cancelled, ErrorCode::RoundCancelled);
+
+        require!(
+            oracle_proof::proof_verified(
+                &ctx.accounts.instructions,
+                &config.oracle_signer,
+                &request.seed,
+                &proof,
+            ),
+            ErrorCode::InvalidOracleProof
+        );
+
+        let randomness = hash(&proof).to_bytes();
+        request.randomness = randomness;
+        request.fulfilled = true;
+
+        let number = draw_number(lottery, entropy_from(&randomness));
+        record_reveal(lottery, &mut ctx.accounts.round_result, number)
+    }
+
+    pub fn register_winner(ctx: Context<RegisterWinner>) -> Result<()> {
+        let round_result = &mut ctx.accounts.round_result;
+        require!(round_result.settlement_deadline > 0, ErrorCode::RoundNotComplete);
+        require!(!round_result.settled, ErrorCode::RoundAlreadySettled);
+        require!(
+            Clock::get()?.unix_timestamp < round_result.settlement_deadline,
+            ErrorCode::SettlementClosed
+        );
+
+        let ticket = &mut ctx.accounts.ticket;
+        require!(!ticket.registered, ErrorCode::AlreadyRegistered);
+
+        let matches = count_matches(
+            &ticket.numbers,
+            &round_result.revealed_numbers,
+            round_result.match_mode,
+        );
+        require!(round_result.prize_tiers[matches] > 0, ErrorCode::NotWinningTicket);
+
+        ticket.registered = true;
+        round_result.winner_counts[matches] += 1;
+
+        emit!(WinnerRegistered {
+            round: round_result.round,
+            ticket_id: ticket.ticket_id,
+            matches: matches as u8,
+        });
 
         Ok(())
     }
 
-    pub fn claim_prize(ctx: Context<ClaimPrize>, ticket_id: u64) -> Result<()> {
+    pub fn finalize_settlement(ctx: Context<FinalizeSettlement>) -> Result<()> {
         let lottery = &mut ctx.accounts.lottery;
         require!(lottery.round_complete, ErrorCode::RoundNotComplete);
-        require!(ticket_id < lottery.tickets.len() as u64, ErrorCode::InvalidTicketId);
+        require!(!lottery.round_settled, ErrorCode::RoundAlreadySettled);
+
+        let round_result = &mut ctx.accounts.round_result;
+        require!(
+            Clock::get()?.unix_timestamp >= round_result.settlement_deadline,
+            ErrorCode::SettlementStillOpen
+        );
+
+        lottery.round_settled = true;
+        round_result.settled = true;
+
+        // Every registered winner's share is set aside until claimed, so later
+        // rounds can't pay it out again
+        let owed = (0..round_result.winner_counts.len())
+            .map(|matches| {
+                winner_share(round_result, matches) * round_result.winner_counts[matches] as u64
+            })
+            .sum::<u64>();
+        lottery.outstanding_liabilities = lottery.outstanding_liabilities.checked_add(owed).unwrap();
+
+        emit!(RoundSettled {
+            round: round_result.round,
+            winner_counts: round_result.winner_counts,
+        });
 
-        let revealed_numbers = lottery.revealed_numbers;
-        let ticket = &mut lottery.tickets[ticket_id as usize];
+        Ok(())
+    }
+
+    pub fn claim_prize(ctx: Context<ClaimPrize>) -> Result<()> {
+        require!(ctx.accounts.config.paused & PAUSE_CLAIM == 0, ErrorCode::Paused);
+        // Checked against the ticket's own round, which may be long finished
+        let round_result = &ctx.accounts.round_result;
+        require!(round_result.settled, ErrorCode::RoundNotSettled);
+
+        let ticket = &mut ctx.accounts.ticket;
         require!(!ticket.claimed, ErrorCode::PrizeAlreadyClaimed);
         require!(*ctx.accounts.user.key == ticket.owner, ErrorCode::NotTicketOwner);
+        require!(ticket.registered, ErrorCode::TicketNotRegistered);
 
-        let mut is_winner = true;
-        for i in 0..6 {
-            if ticket.numbers[i] != revealed_numbers[i] {
-                is_winner = false;
-                break;
-            }
-        }
-
-        require!(is_winner, ErrorCode::NotWinningTicket);
+        let matches = count_matches(
+            &ticket.numbers,
+            &round_result.revealed_numbers,
+            round_result.match_mode,
+        );
 
         ticket.claimed = true;
-        let prize = lottery.accumulated_prize;
-        lottery.accumulated_prize = 0;
+        // Registered winners split their tier's share equally; whatever the
+        // tiers don't pay out rolls over into the next round
+        let prize = winner_share(round_result, matches);
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.accumulated_prize = lottery
+            .accumulated_prize
+            .checked_sub(prize)
+            .ok_or(ErrorCode::InsufficientPrizePool)?;
+        lottery.outstanding_liabilities = lottery
+            .outstanding_liabilities
+            .checked_sub(prize)
+            .ok_or(ErrorCode::InsufficientPrizePool)?;
 
         **ctx.accounts.lottery.to_account_info().try_borrow_mut_lamports()? -= prize;
         **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += prize;
@@ -127,15 +451,117 @@ pub mod sequential_lottery {
         Ok(())
     }
 
+    pub fn cancel_round(ctx: Context<CancelRound>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        require!(!lottery.round_complete, ErrorCode::RoundComplete);
+        require!(!lottery.round_cancelled, ErrorCode::RoundCancelled);
+
+        // Anyone can cancel a stalled round; an open one needs the admin
+        let config = &ctx.accounts.config;
+        let cancel_timeout = config.reveal_interval * CANCEL_TIMEOUT_INTERVALS;
+        if Clock::get()?.unix_timestamp < lottery.last_reveal_time + cancel_timeout {
+            authorize(
+                config,
+                config.admin,
+                ctx.accounts.caller.key(),
+                &mut ctx.accounts.proposal,
+                ProposalAction::CancelRound {
+                    round: lottery.current_round,
+                },
+            )?;
+        }
+
+        lottery.round_cancelled = true;
+        ctx.accounts.round_result.cancelled = true;
+
+        // Refunds return the whole ticket price, so the round's mint fees go
+        // back into the pool they're refunded from
+        lottery.protocol_fees_accrued = lottery
+            .protocol_fees_accrued
+            .checked_sub(lottery.round_mint_fees)
+            .ok_or(ErrorCode::InsufficientFees)?;
+        lottery.accumulated_prize = lottery
+            .accumulated_prize
+            .checked_add(lottery.round_mint_fees)
+            .unwrap();
+        lottery.round_mint_fees = 0;
+
+        // Every ticket in the round is now owed its price back, even after
+        // the next round starts
+        lottery.outstanding_liabilities = lottery
+            .outstanding_liabilities
+            .checked_add(lottery.round_ticket_sales)
+            .unwrap();
+
+        emit!(RoundCancelled {
+            round: lottery.current_round,
+        });
+
+        Ok(())
+    }
+
+    pub fn refund_ticket(ctx: Context<RefundTicket>) -> Result<()> {
+        require!(ctx.accounts.round_result.cancelled, ErrorCode::RoundNotCancelled);
+
+        let ticket = &mut ctx.accounts.ticket;
+        require!(*ctx.accounts.user.key == ticket.owner, ErrorCode::NotTicketOwner);
+        require!(!ticket.refunded, ErrorCode::AlreadyRefunded);
+
+        // The full ticket price, owner fee included
+        ticket.refunded = true;
+        let refund = ticket.prize_contribution.checked_add(ticket.owner_fee).unwrap();
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.accumulated_prize = lottery
+            .accumulated_prize
+            .checked_sub(refund)
+            .ok_or(ErrorCode::InsufficientPrizePool)?;
+        lottery.outstanding_liabilities = lottery
+            .outstanding_liabilities
+            .checked_sub(refund)
+            .ok_or(ErrorCode::InsufficientPrizePool)?;
+
+        **ctx.accounts.lottery.to_account_info().try_borrow_mut_lamports()? -= refund;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += refund;
+
+        emit!(TicketRefunded {
+            ticket_id: ticket.ticket_id,
+            owner: *ctx.accounts.user.key,
+            amount: refund,
+        });
+
+        Ok(())
+    }
+
     pub fn start_new_round(ctx: Context<StartNewRound>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        authorize(
+            config,
+            config.admin,
+            ctx.accounts.admin.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::StartNewRound {
+                round: ctx.accounts.lottery.current_round + 1,
+            },
+        )?;
+
         let lottery = &mut ctx.accounts.lottery;
-        require!(lottery.round_complete, ErrorCode::RoundNotComplete);
+        require!(
+            lottery.round_settled || lottery.round_cancelled,
+            ErrorCode::RoundNotSettled
+        );
 
         lottery.current_round += 1;
         lottery.current_reveal_index = 0;
         lottery.round_complete = false;
+        lottery.round_settled = false;
+        lottery.round_cancelled = false;
+        lottery.next_ticket_id = 0;
+        lottery.round_ticket_sales = 0;
+        lottery.revealed_numbers = [0; 6];
         lottery.last_reveal_time = Clock::get()?.unix_timestamp;
 
+        open_round_result(&mut ctx.accounts.round_result, lottery, ctx.bumps.round_result);
+
         emit!(NewRoundStarted {
             round: lottery.current_round,
         });
@@ -143,35 +569,285 @@ pub mod sequential_lottery {
         Ok(())
     }
 
-    // For listing a ticket
-    pub fn list_ticket(ctx: Context<ListTicket>, ticket_id: u64, price: u64) -> Result<()> {
+    pub fn grant_role(mut ctx: Context<UpdateConfig>, role: Role, account: Pubkey) -> Result<()> {
+        require!(account != Pubkey::default(), ErrorCode::InvalidRoleAccount);
+        authorize_admin(&mut ctx, ProposalAction::GrantRole { role, account })?;
+
+        let config = &mut ctx.accounts.config;
+        match role {
+            Role::Admin => return err!(ErrorCode::AdminNeedsTwoStepTransfer),
+            Role::Revealer => config.revealer = account,
+            Role::FeeCollector => config.fee_collector = account,
+        }
+
+        emit!(RoleGranted { role, account });
+
+        Ok(())
+    }
+
+    // Leaves the role empty; the admin role can only be handed over, not revoked
+    pub fn revoke_role(mut ctx: Context<UpdateConfig>, role: Role) -> Result<()> {
+        authorize_admin(&mut ctx, ProposalAction::RevokeRole { role })?;
+
+        let config = &mut ctx.accounts.config;
+        let account = match role {
+            Role::Admin => return err!(ErrorCode::CannotRevokeAdmin),
+            Role::Revealer => std::mem::take(&mut config.revealer),
+            Role::FeeCollector => std::mem::take(&mut config.fee_collector),
+        };
+
+        emit!(RoleRevoked { role, account });
+
+        Ok(())
+    }
+
+    // Takes the full set of PAUSE_* bits to halt; 0 resumes everything
+    pub fn set_paused(mut ctx: Context<UpdateConfig>, paused: u8) -> Result<()> {
+        authorize_admin(&mut ctx, ProposalAction::SetPaused { paused })?;
+
+        ctx.accounts.config.paused = paused;
+
+        emit!(PauseUpdated { paused });
+
+        Ok(())
+    }
+
+    // Proposing the default pubkey withdraws a pending proposal
+    pub fn propose_authority(mut ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        authorize_admin(&mut ctx, ProposalAction::ProposeAuthority { new_admin })?;
+
+        let config = &mut ctx.accounts.config;
+        config.pending_admin = new_admin;
+
+        emit!(AuthorityProposed {
+            admin: config.admin,
+            pending_admin: new_admin,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let previous_admin = config.admin;
+        config.admin = config.pending_admin;
+        config.pending_admin = Pubkey::default();
+
+        emit!(AuthorityTransferred {
+            previous_admin,
+            new_admin: config.admin,
+        });
+
+        Ok(())
+    }
+
+    // Takes effect immediately, including for the round in progress
+    pub fn update_config(mut ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        require!(params.mint_price > 0, ErrorCode::InvalidConfig);
+        require!(
+            params.owner_fee_bps.saturating_add(params.marketplace_fee_bps) <= MAX_TOTAL_FEE_BPS,
+            ErrorCode::InvalidConfig
+        );
+        require!(
+            (MIN_REVEAL_INTERVAL..=MAX_REVEAL_INTERVAL).contains(&params.reveal_interval),
+            ErrorCode::InvalidConfig
+        );
+        authorize_admin(
+            &mut ctx,
+            ProposalAction::UpdateConfig {
+                params_hash: hash(&params.try_to_vec()?).to_bytes(),
+            },
+        )?;
+
+        let config = &mut ctx.accounts.config;
+        let before = ConfigParams {
+            mint_price: config.mint_price,
+            owner_fee_bps: config.owner_fee_bps,
+            marketplace_fee_bps: config.marketplace_fee_bps,
+            reveal_interval: config.reveal_interval,
+        };
+        config.mint_price = params.mint_price;
+        config.owner_fee_bps = params.owner_fee_bps;
+        config.marketplace_fee_bps = params.marketplace_fee_bps;
+        config.reveal_interval = params.reveal_interval;
+
+        emit!(ConfigUpdated {
+            before,
+            after: params,
+        });
+
+        Ok(())
+    }
+
+    // Sends every accrued fee to the fee collector. Prize funds and the
+    // account's rent reserve are never part of the accrued totals.
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        authorize(
+            config,
+            config.fee_collector,
+            ctx.accounts.caller.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::WithdrawFees,
+        )?;
+
+        // Mint fees from a round that could still be cancelled stay behind
         let lottery = &mut ctx.accounts.lottery;
-        require!(ticket_id < lottery.tickets.len() as u64, ErrorCode::InvalidTicketId);
-        
-        let ticket = &lottery.tickets[ticket_id as usize];
+        let protocol_amount = lottery
+            .protocol_fees_accrued
+            .checked_sub(lottery.round_mint_fees)
+            .ok_or(ErrorCode::InsufficientFees)?;
+        let marketplace_amount = lottery.marketplace_fees_accrued;
+        let amount = protocol_amount
+            .checked_add(marketplace_amount)
+            .ok_or(ErrorCode::InsufficientFees)?;
+        require!(amount > 0, ErrorCode::InsufficientFees);
+
+        // Belt and braces: whatever is left must still cover prizes and rent
+        let lottery_info = lottery.to_account_info();
+        let reserved = Rent::get()?
+            .minimum_balance(lottery_info.data_len())
+            .checked_add(lottery.accumulated_prize)
+            .ok_or(ErrorCode::InsufficientFees)?;
+        require!(
+            lottery_info.lamports().saturating_sub(amount) >= reserved,
+            ErrorCode::InsufficientFees
+        );
+
+        lottery.protocol_fees_accrued = lottery.round_mint_fees;
+        lottery.marketplace_fees_accrued = 0;
+
+        **lottery_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.fee_collector.to_account_info().try_borrow_mut_lamports()? += amount;
+
+        emit!(FeesWithdrawn {
+            recipient: ctx.accounts.fee_collector.key(),
+            protocol_amount,
+            marketplace_amount,
+        });
+
+        Ok(())
+    }
+
+    // A threshold of 0 turns the multisig off and hands privileged operations
+    // back to the role holders; anything else requires approved proposals
+    pub fn set_approvers(
+        mut ctx: Context<UpdateConfig>,
+        approvers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        require!(
+            approvers.len() <= MAX_APPROVERS && threshold as usize <= approvers.len(),
+            ErrorCode::InvalidApprovers
+        );
+        for (i, approver) in approvers.iter().enumerate() {
+            require!(
+                *approver != Pubkey::default() && !approvers[..i].contains(approver),
+                ErrorCode::InvalidApprovers
+            );
+        }
+
+        let approver_keys: Vec<&[u8]> = approvers.iter().map(|key| key.as_ref()).collect();
+        authorize_admin(
+            &mut ctx,
+            ProposalAction::SetApprovers {
+                approvers_hash: hashv(&approver_keys).to_bytes(),
+                threshold,
+            },
+        )?;
+
+        let mut approver_set = [Pubkey::default(); MAX_APPROVERS];
+        approver_set[..approvers.len()].copy_from_slice(&approvers);
+
+        // Bumping the epoch voids every proposal approved by the old set
+        let config = &mut ctx.accounts.config;
+        config.approvers = approver_set;
+        config.approver_count = approvers.len() as u8;
+        config.threshold = threshold;
+        config.approvers_epoch += 1;
+
+        emit!(ApproversUpdated {
+            approvers,
+            threshold,
+            approvers_epoch: config.approvers_epoch,
+        });
+
+        Ok(())
+    }
+
+    // Creating a proposal counts as the proposer's approval
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: ProposalAction) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(config.threshold > 0, ErrorCode::MultisigDisabled);
+        let approver_index = approver_index(config, &ctx.accounts.approver.key())?;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.id = config.proposal_count;
+        proposal.proposer = ctx.accounts.approver.key();
+        proposal.action = action.clone();
+        proposal.approvals = 1 << approver_index;
+        proposal.approvers_epoch = config.approvers_epoch;
+        proposal.executed = false;
+        proposal.bump = ctx.bumps.proposal;
+
+        config.proposal_count += 1;
+
+        emit!(ProposalCreated {
+            proposal_id: proposal.id,
+            proposer: proposal.proposer,
+            action,
+        });
+
+        Ok(())
+    }
+
+    pub fn approve_proposal(ctx: Context<ApproveProposal>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, ErrorCode::ProposalAlreadyExecuted);
+        require!(
+            proposal.approvers_epoch == config.approvers_epoch,
+            ErrorCode::StaleProposal
+        );
+
+        let approver_bit = 1 << approver_index(config, &ctx.accounts.approver.key())?;
+        require!(proposal.approvals & approver_bit == 0, ErrorCode::AlreadyApproved);
+        proposal.approvals |= approver_bit;
+
+        emit!(ProposalApproved {
+            proposal_id: proposal.id,
+            approver: ctx.accounts.approver.key(),
+            approvals: proposal.approvals.count_ones() as u8,
+        });
+
+        Ok(())
+    }
+
+    // For listing a ticket
+    pub fn list_ticket(ctx: Context<ListTicket>, price: u64) -> Result<()> {
+        require!(ctx.accounts.config.paused & PAUSE_MARKETPLACE == 0, ErrorCode::Paused);
+        let ticket = &mut ctx.accounts.ticket;
         require!(*ctx.accounts.user.key == ticket.owner, ErrorCode::NotTicketOwner);
         require!(price > 0, ErrorCode::InvalidPrice);
+        require!(
+            listing_open(&ctx.accounts.lottery, ticket),
+            ErrorCode::ListingExpired
+        );
 
-        // Check if ticket is already listed
-        let existing_listing = lottery.marketplace_listings.iter()
-            .position(|listing| listing.ticket_id == ticket_id);
-        
-        if let Some(pos) = existing_listing {
-            // Update existing listing
-            lottery.marketplace_listings[pos].price = price;
-            lottery.marketplace_listings[pos].active = true;
-        } else {
-            // Add new listing
-            lottery.marketplace_listings.push(MarketplaceListing {
-                ticket_id,
-                seller: *ctx.accounts.user.key,
-                price,
-                active: true,
-            });
-        }
+        // Locks the ticket against offers until it's sold or delisted. A listing
+        // can't be bought once its round is over, so claims and refunds ignore it
+        ticket.listed = true;
+
+        let listing = &mut ctx.accounts.listing;
+        listing.lottery = ticket.lottery;
+        listing.round = ticket.round;
+        listing.ticket_id = ticket.ticket_id;
+        listing.seller = *ctx.accounts.user.key;
+        listing.price = price;
+        listing.bump = ctx.bumps.listing;
 
         emit!(TicketListed {
-            ticket_id,
+            ticket_id: ticket.ticket_id,
             seller: *ctx.accounts.user.key,
             price,
         });
@@ -179,25 +855,12 @@ pub mod sequential_lottery {
         Ok(())
     }
 
-    // For removing a listing
-    pub fn delist_ticket(ctx: Context<DelistTicket>, ticket_id: u64) -> Result<()> {
-        let lottery = &mut ctx.accounts.lottery;
-        require!(ticket_id < lottery.tickets.len() as u64, ErrorCode::InvalidTicketId);
-        
-        // Find the listing
-        let listing_position = lottery.marketplace_listings.iter()
-            .position(|listing| listing.ticket_id == ticket_id && listing.active)
-            .ok_or(ErrorCode::TicketNotListed)?;
-        
-        let listing = &lottery.marketplace_listings[listing_position];
-        require!(listing.seller == *ctx.accounts.user.key, ErrorCode::NotSeller);
-
-        // Set active to false or remove entirely
-        lottery.marketplace_listings[listing_position].active = false;
-        // Alternatively: lottery.marketplace_listings.remove(listing_position);
+    // For removing a listing; closing the account refunds its rent
+    pub fn delist_ticket(ctx: Context<DelistTicket>) -> Result<()> {
+        ctx.accounts.ticket.listed = false;
 
         emit!(TicketDelisted {
-            ticket_id,
+            ticket_id: ctx.accounts.listing.ticket_id,
             seller: *ctx.accounts.user.key,
         });
 
@@ -205,41 +868,67 @@ pub mod sequential_lottery {
     }
 
     // For buying a ticket
-    pub fn buy_ticket(ctx: Context<BuyTicket>, ticket_id: u64) -> Result<()> {
-        let lottery = &mut ctx.accounts.lottery;
-        
-        // Find the listing
-        let listing_position = lottery.marketplace_listings.iter()
-            .position(|listing| listing.ticket_id == ticket_id && listing.active)
-            .ok_or(ErrorCode::TicketNotListed)?;
-        
-        let listing = &lottery.marketplace_listings[listing_position];
+    pub fn buy_ticket(ctx: Context<BuyTicket>) -> Result<()> {
+        require!(ctx.accounts.config.paused & PAUSE_MARKETPLACE == 0, ErrorCode::Paused);
+        let listing = &ctx.accounts.listing;
         let price = listing.price;
         let seller = listing.seller;
         
         require!(ctx.accounts.user.lamports() >= price, ErrorCode::InsufficientFunds);
         require!(seller != *ctx.accounts.user.key, ErrorCode::CannotBuyOwnTicket);
+        // A listing goes stale once its round completes or is cancelled
+        require!(
+            listing_open(&ctx.accounts.lottery, &ctx.accounts.ticket),
+            ErrorCode::ListingExpired
+        );
+        require!(ctx.accounts.ticket.owner == seller, ErrorCode::SellerNotOwner);
 
-        // Calculate fees
-        let owner_fee = price.checked_mul(OWNER_FEE).unwrap() / 10000;
-        let marketplace_fee = price.checked_mul(MARKETPLACE_FEE).unwrap() / 10000;
+        let (owner_fee, marketplace_fee) = sale_fees(&ctx.accounts.config, price);
         let total_fee = owner_fee.checked_add(marketplace_fee).unwrap();
         let seller_amount = price.checked_sub(total_fee).unwrap();
 
-        // Update ticket ownership
-        lottery.tickets[ticket_id as usize].owner = *ctx.accounts.user.key;
-        
-        // Mark listing as inactive
-        lottery.marketplace_listings[listing_position].active = false;
+        // Update ticket ownership; the listing is closed back to the seller
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.owner = *ctx.accounts.user.key;
+        ticket.listed = false;
 
-        // Transfer funds
-        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? -= price;
-        **ctx.accounts.lottery.to_account_info().try_borrow_mut_lamports()? += owner_fee;
-        **ctx.accounts.marketplace.to_account_info().try_borrow_mut_lamports()? += marketplace_fee;
-        **ctx.accounts.seller.to_account_info().try_borrow_mut_lamports()? += seller_amount;
+        // Both fees stay in the lottery account until withdraw_fees
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.protocol_fees_accrued = lottery.protocol_fees_accrued.checked_add(owner_fee).unwrap();
+        lottery.marketplace_fees_accrued = lottery
+            .marketplace_fees_accrued
+            .checked_add(marketplace_fee)
+            .unwrap();
+
+        // The buyer is a system account, so only the system program can debit it
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.lottery.to_account_info(),
+                },
+            ),
+            total_fee,
+        )?;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            seller_amount,
+        )?;
+
+        emit!(FeesAccrued {
+            protocol_fee: owner_fee,
+            marketplace_fee,
+        });
 
         emit!(TicketSold {
-            ticket_id,
+            ticket_id: ticket.ticket_id,
             seller,
             buyer: *ctx.accounts.user.key,
             price,
@@ -247,6 +936,141 @@ pub mod sequential_lottery {
 
         Ok(())
     }
+
+    // Escrows `amount` in the offer account until it's accepted, cancelled or expires
+    pub fn place_offer(
+        ctx: Context<PlaceOffer>,
+        nonce: u64,
+        target: OfferTarget,
+        amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(ctx.accounts.config.paused & PAUSE_MARKETPLACE == 0, ErrorCode::Paused);
+        let lottery = &ctx.accounts.lottery;
+        require!(
+            !lottery.round_complete && !lottery.round_cancelled,
+            ErrorCode::ListingExpired
+        );
+        require!(amount > 0, ErrorCode::InvalidPrice);
+        require!(expires_at > Clock::get()?.unix_timestamp, ErrorCode::InvalidOffer);
+        if let OfferTarget::Prefix { length } = target {
+            require!(
+                length > 0 && length <= lottery.current_reveal_index,
+                ErrorCode::InvalidOffer
+            );
+        }
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.buyer.to_account_info(),
+                    to: ctx.accounts.offer.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let offer = &mut ctx.accounts.offer;
+        offer.lottery = lottery.key();
+        offer.round = lottery.current_round;
+        offer.buyer = ctx.accounts.buyer.key();
+        offer.nonce = nonce;
+        offer.target = target.clone();
+        offer.amount = amount;
+        offer.expires_at = expires_at;
+        offer.bump = ctx.bumps.offer;
+
+        emit!(OfferPlaced {
+            round: offer.round,
+            buyer: offer.buyer,
+            nonce,
+            target,
+            amount,
+            expires_at,
+        });
+
+        Ok(())
+    }
+
+    // The buyer can cancel any time; anyone can clear an offer that can no
+    // longer be accepted. The escrow and rent always go back to the buyer.
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        if ctx.accounts.caller.key() != offer.buyer {
+            let lottery = &ctx.accounts.lottery;
+            let round_open = offer.round == lottery.current_round
+                && !lottery.round_complete
+                && !lottery.round_cancelled;
+            require!(
+                !round_open || Clock::get()?.unix_timestamp >= offer.expires_at,
+                ErrorCode::OfferStillActive
+            );
+        }
+
+        emit!(OfferCancelled {
+            buyer: offer.buyer,
+            nonce: offer.nonce,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+        require!(ctx.accounts.config.paused & PAUSE_MARKETPLACE == 0, ErrorCode::Paused);
+        let offer = &ctx.accounts.offer;
+        let ticket = &ctx.accounts.ticket;
+        require!(
+            Clock::get()?.unix_timestamp < offer.expires_at,
+            ErrorCode::OfferExpired
+        );
+        require!(
+            listing_open(&ctx.accounts.lottery, ticket) && ticket.round == offer.round,
+            ErrorCode::ListingExpired
+        );
+        require!(!ticket.listed, ErrorCode::TicketIsListed);
+        require!(offer.buyer != ticket.owner, ErrorCode::CannotBuyOwnTicket);
+        require!(
+            offer_matches(&offer.target, ticket, &ctx.accounts.lottery),
+            ErrorCode::OfferMismatch
+        );
+
+        let price = offer.amount;
+        let (owner_fee, marketplace_fee) = sale_fees(&ctx.accounts.config, price);
+        let total_fee = owner_fee.checked_add(marketplace_fee).unwrap();
+        let seller_amount = price.checked_sub(total_fee).unwrap();
+
+        // The offer account is ours, so its escrow can be moved directly;
+        // closing it afterwards returns the rent to the buyer
+        **ctx.accounts.offer.to_account_info().try_borrow_mut_lamports()? -= price;
+        **ctx.accounts.lottery.to_account_info().try_borrow_mut_lamports()? += total_fee;
+        **ctx.accounts.seller.to_account_info().try_borrow_mut_lamports()? += seller_amount;
+
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.protocol_fees_accrued = lottery.protocol_fees_accrued.checked_add(owner_fee).unwrap();
+        lottery.marketplace_fees_accrued = lottery
+            .marketplace_fees_accrued
+            .checked_add(marketplace_fee)
+            .unwrap();
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.owner = ctx.accounts.offer.buyer;
+
+        emit!(FeesAccrued {
+            protocol_fee: owner_fee,
+            marketplace_fee,
+        });
+
+        emit!(OfferAccepted {
+            ticket_id: ticket.ticket_id,
+            seller: ctx.accounts.seller.key(),
+            buyer: ticket.owner,
+            nonce: ctx.accounts.offer.nonce,
+            price,
+        });
+
+        Ok(())
+    }
 }
 
 #[account]
@@ -255,29 +1079,184 @@ pub struct Lottery {
     pub current_reveal_index: u8,
     pub last_reveal_time: i64,
     pub accumulated_prize: u64,
+    pub outstanding_liabilities: u64, // Owed out of accumulated_prize to past winners and refunds
+    pub round_ticket_sales: u64, // What this round's tickets cost, owed back if it's cancelled
+    pub protocol_fees_accrued: u64, // Owner fees held in this account, not part of any prize
+    pub round_mint_fees: u64, // This round's mint fees, held back while it can still be cancelled
+    pub marketplace_fees_accrued: u64, // Marketplace fees held in this account
     pub round_complete: bool,
-    pub tickets: Vec<Ticket>,
-    pub revealed_numbers: [u8; 6],
-    // Replace HashMap/BTreeMap with a Vec of tuples (or a custom struct)
-    pub marketplace_listings: Vec<MarketplaceListing>,
+    pub next_ticket_id: u64, // Ticket ids restart at 0 every round
+    pub revealed_numbers: [u8; 6], // Current round only; archived in its RoundResult
+    pub prize_tiers: [u16; 7], // Basis points of the round's pool, indexed by match count
+    pub round_settled: bool,
+    pub round_cancelled: bool,
+    pub sales_close_index: u8, // Minting stops once this many numbers are revealed
+    pub price_multipliers: [u32; 6], // Basis points of Config::mint_price per reveal index
+    pub min_number: u8,
+    pub max_number: u8, // Inclusive
+    pub allow_repeats: bool, // Otherwise draws sample without replacement
+    pub match_mode: MatchMode,
     pub bump: u8,
 }
 
-// #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+// Who may do what. An empty (default) key means nobody holds the role.
 #[account]
-pub struct MarketplaceListing {
-    pub ticket_id: u64,
-    pub seller: Pubkey,
-    pub price: u64,
-    pub active: bool,
+pub struct Config {
+    pub admin: Pubkey, // Grants and revokes roles, starts rounds, cancels rounds early
+    pub pending_admin: Pubkey, // Becomes admin once it calls accept_authority
+    pub revealer: Pubkey, // Reveals numbers
+    pub fee_collector: Pubkey, // Withdraws accrued fees
+    pub paused: u8, // PAUSE_* bits of the operations currently halted
+    pub approvers: [Pubkey; MAX_APPROVERS], // First approver_count entries are in use
+    pub approver_count: u8,
+    pub threshold: u8, // Approvals a proposal needs; 0 means the multisig is off
+    pub approvers_epoch: u64, // Bumped whenever the approver set changes
+    pub proposal_count: u64, // Proposals are seeded by ids 0..proposal_count
+    pub mint_price: u64, // Lamports before the reveal index multiplier
+    pub owner_fee_bps: u16, // Taken from mints and marketplace sales
+    pub marketplace_fee_bps: u16, // Taken from marketplace sales for the fee collector
+    pub reveal_interval: i64, // Seconds between reveals
+    pub oracle_program: Pubkey, // Default key when reveals use slot hashes
+    pub oracle_signer: Pubkey, // Key whose ed25519 signature is the oracle proof
+    pub bump: u8,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct Ticket {
-    pub numbers: [u8; 6],
-    pub claimed: bool,
-    pub owner: Pubkey,
-    pub mint_time: i64,
+// A privileged operation waiting on approvals. The instruction it describes
+// executes it by taking the proposal in place of the role holder's signature.
+#[account]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+    pub approvals: u8, // Bit i set when approvers[i] has approved
+    pub approvers_epoch: u64, // Approver set the approvals were collected from
+    pub executed: bool,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum ProposalAction {
+    RevealNumber { round: u64, reveal_index: u8 },
+    StartNewRound { round: u64 },
+    CancelRound { round: u64 },
+    GrantRole { role: Role, account: Pubkey },
+    RevokeRole { role: Role },
+    SetPaused { paused: u8 },
+    ProposeAuthority { new_admin: Pubkey },
+    SetApprovers { approvers_hash: [u8; 32], threshold: u8 }, // sha256 of the keys in order
+    UpdateConfig { params_hash: [u8; 32] }, // sha256 of the borsh-encoded ConfigParams
+    WithdrawFees,
+    ConfigureOracle { oracle_program: Pubkey, oracle_signer: Pubkey },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    OrderedExact, // Same number in the same position
+    UnorderedSet, // Distinct picked numbers revealed anywhere, classic lotto
+    OrderedPrefix, // Positions matched from the first reveal until the first miss
+}
+
+impl From<MatchMode> for Rule {
+    fn from(match_mode: MatchMode) -> Self {
+        match match_mode {
+            MatchMode::OrderedExact => Rule::OrderedExact,
+            MatchMode::UnorderedSet => Rule::UnorderedSet,
+            MatchMode::OrderedPrefix => Rule::OrderedPrefix,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum Role {
+    Admin,
+    Revealer,
+    FeeCollector,
+}
+
+// Seeded by lottery and round; everything claims and refunds need once the
+// lottery has moved on to a later round
+#[account]
+pub struct RoundResult {
+    pub lottery: Pubkey,
+    pub round: u64,
+    pub revealed_numbers: [u8; 6],
+    pub prize_tiers: [u16; 7], // Copied from the lottery when the round opened
+    pub match_mode: MatchMode, // Likewise
+    pub prize_pool: u64, // Unowed part of accumulated_prize when the round completed
+    pub winner_counts: [u32; 7], // Registered winners per match count
+    pub settlement_deadline: i64, // Winners must register before this; 0 until complete
+    pub settled: bool,
+    pub cancelled: bool,
+    pub bump: u8,
+}
+
+// Seeded by lottery, round and ticket id, like the ticket it lists
+#[account]
+pub struct MarketplaceListing {
+    pub lottery: Pubkey,
+    pub round: u64,
+    pub ticket_id: u64,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct Ticket {
+    pub lottery: Pubkey,
+    pub round: u64,
+    pub ticket_id: u64,
+    pub numbers: [u8; 6],
+    pub claimed: bool,
+    pub registered: bool,
+    pub refunded: bool,
+    pub prize_contribution: u64, // Refunded if the round is cancelled
+    pub owner_fee: u64, // Likewise
+    pub listed: bool, // Locked in a marketplace listing; void once its round is over
+    pub owner: Pubkey,
+    pub mint_time: i64,
+    pub bump: u8,
+}
+
+// One per reveal made through the oracle, seeded by lottery, round and index
+#[account]
+pub struct RevealRequest {
+    pub lottery: Pubkey,
+    pub round: u64,
+    pub reveal_index: u8,
+    pub seed: [u8; 32], // Message the oracle must sign
+    pub randomness: [u8; 32], // Hash of the oracle proof, set on fulfillment
+    pub requested_at: i64,
+    pub fulfilled: bool,
+    pub bump: u8,
+}
+
+// One per (round, sequence); it existing means the sequence is taken. Seeded
+// by match_rules::sequence_key, so permutations of a set are one sequence.
+#[account]
+pub struct SequenceClaim {
+    pub ticket: Pubkey,
+}
+
+// A buyer's escrowed bid, seeded by buyer and a buyer-chosen nonce. The
+// escrowed lamports sit in this account on top of its rent.
+#[account]
+pub struct Offer {
+    pub lottery: Pubkey,
+    pub round: u64,
+    pub buyer: Pubkey,
+    pub nonce: u64,
+    pub target: OfferTarget,
+    pub amount: u64,
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum OfferTarget {
+    Ticket { ticket_id: u64 },
+    // Any ticket in the round matching the first `length` revealed numbers
+    Prefix { length: u8 },
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -297,15 +1276,69 @@ pub struct Initialize<'info> {
         bump
     )]
     pub lottery: Account<'info, Lottery>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + std::mem::size_of::<RoundResult>(),
+        seeds = [b"round", lottery.key().as_ref(), &0u64.to_le_bytes()],
+        bump
+    )]
+    pub round_result: Account<'info, RoundResult>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + std::mem::size_of::<Config>(),
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    // Only the upgrade authority can initialize, so nobody can front-run the
+    // deployer and take every role
     #[account(mut)]
     pub user: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::SequentialLottery>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(user.key())
+            @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
+#[instruction(numbers: [u8; 6])]
 pub struct MintTicket<'info> {
     #[account(mut)]
     pub lottery: Account<'info, Lottery>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + std::mem::size_of::<Ticket>(),
+        seeds = [
+            b"ticket",
+            lottery.key().as_ref(),
+            &lottery.current_round.to_le_bytes(),
+            &lottery.next_ticket_id.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + std::mem::size_of::<SequenceClaim>(),
+        seeds = [
+            b"sequence",
+            lottery.key().as_ref(),
+            &lottery.current_round.to_le_bytes(),
+            sequence_key(&numbers, lottery.match_mode.into()).as_ref(),
+        ],
+        bump
+    )]
+    pub sequence_claim: Account<'info, SequenceClaim>,
     #[account(mut)]
     pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
@@ -317,15 +1350,164 @@ pub struct MintTicket<'info> {
 pub struct RevealNumber<'info> {
     #[account(mut)]
     pub lottery: Account<'info, Lottery>,
-    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump = round_result.bump,
+    )]
+    pub round_result: Account<'info, RoundResult>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    // Checked against the revealer role, or unused when a proposal is executed
+    pub revealer: Signer<'info>,
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
     /// CHECK: Slot hashes account for randomness
+    #[account(address = slot_hashes::ID)]
     pub slot_hashes: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+pub struct ConfigureOracle<'info> {
+    pub lottery: Account<'info, Lottery>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    // Checked against the admin role, or unused when a proposal is executed
+    pub admin: Signer<'info>,
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct RequestReveal<'info> {
+    pub lottery: Account<'info, Lottery>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = revealer,
+        space = 8 + std::mem::size_of::<RevealRequest>(),
+        seeds = [
+            b"reveal_request",
+            lottery.key().as_ref(),
+            &lottery.current_round.to_le_bytes(),
+            &[lottery.current_reveal_index],
+        ],
+        bump
+    )]
+    pub reveal_request: Account<'info, RevealRequest>,
+    // The revealer, or whoever pays to execute an approved proposal
+    #[account(mut)]
+    pub revealer: Signer<'info>,
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FulfillReveal<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump = round_result.bump,
+    )]
+    pub round_result: Account<'info, RoundResult>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            b"reveal_request",
+            lottery.key().as_ref(),
+            &lottery.current_round.to_le_bytes(),
+            &[lottery.current_reveal_index],
+        ],
+        bump = reveal_request.bump,
+    )]
+    pub reveal_request: Account<'info, RevealRequest>,
+    #[account(
+        constraint = oracle_authority.key()
+            == Pubkey::find_program_address(&[ORACLE_AUTHORITY_SEED], &config.oracle_program).0
+            @ ErrorCode::UnauthorizedOracle
+    )]
+    pub oracle_authority: Signer<'info>,
+    /// CHECK: This is the instructions sysvar, used to find the ed25519 proof check
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
 #[derive(Accounts)]
 pub struct ClaimPrize<'info> {
     #[account(mut)]
     pub lottery: Account<'info, Lottery>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, has_one = lottery)]
+    pub ticket: Account<'info, Ticket>,
+    #[account(
+        seeds = [b"round", lottery.key().as_ref(), &ticket.round.to_le_bytes()],
+        bump = round_result.bump,
+    )]
+    pub round_result: Account<'info, RoundResult>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterWinner<'info> {
+    pub lottery: Account<'info, Lottery>,
+    #[account(mut, has_one = lottery)]
+    pub ticket: Account<'info, Ticket>,
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &ticket.round.to_le_bytes()],
+        bump = round_result.bump,
+    )]
+    pub round_result: Account<'info, RoundResult>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeSettlement<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump = round_result.bump,
+    )]
+    pub round_result: Account<'info, RoundResult>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRound<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump = round_result.bump,
+    )]
+    pub round_result: Account<'info, RoundResult>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub caller: Signer<'info>,
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct RefundTicket<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    #[account(mut, has_one = lottery)]
+    pub ticket: Account<'info, Ticket>,
+    #[account(
+        seeds = [b"round", lottery.key().as_ref(), &ticket.round.to_le_bytes()],
+        bump = round_result.bump,
+    )]
+    pub round_result: Account<'info, RoundResult>,
     #[account(mut)]
     pub user: Signer<'info>,
 }
@@ -334,21 +1516,113 @@ pub struct ClaimPrize<'info> {
 pub struct StartNewRound<'info> {
     #[account(mut)]
     pub lottery: Account<'info, Lottery>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + std::mem::size_of::<RoundResult>(),
+        seeds = [b"round", lottery.key().as_ref(), &(lottery.current_round + 1).to_le_bytes()],
+        bump
+    )]
+    pub round_result: Account<'info, RoundResult>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    // The admin, or whoever pays to execute an approved proposal
+    #[account(mut)]
     pub admin: Signer<'info>,
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ListTicket<'info> {
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    // Checked against the admin role, or unused when a proposal is executed
+    pub admin: Signer<'info>,
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = approver,
+        space = 8 + std::mem::size_of::<Proposal>(),
+        seeds = [b"proposal", &config.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
     #[account(mut)]
+    pub approver: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveProposal<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [b"proposal", &proposal.id.to_le_bytes()], bump = proposal.bump)]
+    pub proposal: Account<'info, Proposal>,
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.pending_admin != Pubkey::default() @ ErrorCode::NoPendingAdmin,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(constraint = new_admin.key() == config.pending_admin @ ErrorCode::Unauthorized)]
+    pub new_admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ListTicket<'info> {
     pub lottery: Account<'info, Lottery>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, has_one = lottery)]
+    pub ticket: Account<'info, Ticket>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + std::mem::size_of::<MarketplaceListing>(),
+        seeds = [
+            b"listing",
+            lottery.key().as_ref(),
+            &ticket.round.to_le_bytes(),
+            &ticket.ticket_id.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub listing: Account<'info, MarketplaceListing>,
     #[account(mut)]
     pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct DelistTicket<'info> {
-    #[account(mut)]
-    pub lottery: Account<'info, Lottery>,
+    #[account(
+        mut,
+        close = user,
+        constraint = listing.seller == user.key() @ ErrorCode::NotSeller,
+    )]
+    pub listing: Account<'info, MarketplaceListing>,
+    #[account(
+        mut,
+        constraint = ticket.lottery == listing.lottery
+            && ticket.round == listing.round
+            && ticket.ticket_id == listing.ticket_id @ ErrorCode::InvalidTicketId,
+    )]
+    pub ticket: Account<'info, Ticket>,
     #[account(mut)]
     pub user: Signer<'info>,
 }
@@ -357,14 +1631,171 @@ pub struct DelistTicket<'info> {
 pub struct BuyTicket<'info> {
     #[account(mut)]
     pub lottery: Account<'info, Lottery>,
+    #[account(mut, has_one = lottery)]
+    pub ticket: Account<'info, Ticket>,
+    #[account(
+        mut,
+        close = seller,
+        has_one = seller,
+        seeds = [
+            b"listing",
+            lottery.key().as_ref(),
+            &ticket.round.to_le_bytes(),
+            &ticket.ticket_id.to_le_bytes(),
+        ],
+        bump = listing.bump,
+    )]
+    pub listing: Account<'info, MarketplaceListing>,
     #[account(mut)]
     pub user: Signer<'info>,
-    /// CHECK: Seller account
+    /// CHECK: Must be the listing's seller, enforced by has_one on the listing
     #[account(mut)]
     pub seller: AccountInfo<'info>,
-    /// CHECK: Marketplace account
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(nonce: u64)]
+pub struct PlaceOffer<'info> {
+    pub lottery: Account<'info, Lottery>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + std::mem::size_of::<Offer>(),
+        seeds = [b"offer", buyer.key().as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
     #[account(mut)]
-    pub marketplace: AccountInfo<'info>,
+    pub buyer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    pub lottery: Account<'info, Lottery>,
+    #[account(
+        mut,
+        close = buyer,
+        has_one = lottery,
+        has_one = buyer,
+        seeds = [b"offer", buyer.key().as_ref(), &offer.nonce.to_le_bytes()],
+        bump = offer.bump,
+    )]
+    pub offer: Account<'info, Offer>,
+    /// CHECK: Gets the escrow and rent back; has_one ties it to the offer
+    #[account(mut)]
+    pub buyer: AccountInfo<'info>,
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        has_one = lottery,
+        constraint = ticket.owner == seller.key() @ ErrorCode::NotTicketOwner,
+    )]
+    pub ticket: Account<'info, Ticket>,
+    #[account(
+        mut,
+        close = buyer,
+        has_one = lottery,
+        has_one = buyer,
+        seeds = [b"offer", buyer.key().as_ref(), &offer.nonce.to_le_bytes()],
+        bump = offer.bump,
+    )]
+    pub offer: Account<'info, Offer>,
+    /// CHECK: Gets the offer account's rent back; has_one ties it to the offer
+    #[account(mut)]
+    pub buyer: AccountInfo<'info>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(mut, seeds = [b"lottery"], bump = lottery.bump)]
+    pub lottery: Account<'info, Lottery>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, address = config.fee_collector @ ErrorCode::Unauthorized)]
+    pub fee_collector: SystemAccount<'info>,
+    // Checked against the fee collector role, or unused when a proposal is executed
+    pub caller: Signer<'info>,
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct InitializeParams {
+    pub prize_tiers: [u16; 7], // Basis points of the round's pool, indexed by match count
+    pub sales_close_index: u8, // Minting stops once this many numbers are revealed
+    pub price_multipliers: [u32; 6], // Basis points of Config::mint_price per reveal index
+    pub min_number: u8,
+    pub max_number: u8, // Inclusive
+    pub allow_repeats: bool, // Whether a number may appear twice on one ticket
+    pub match_mode: MatchMode,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigParams {
+    pub mint_price: u64,
+    pub owner_fee_bps: u16,
+    pub marketplace_fee_bps: u16,
+    pub reveal_interval: i64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub before: ConfigParams,
+    pub after: ConfigParams,
+}
+
+#[event]
+pub struct FeesAccrued {
+    pub protocol_fee: u64,
+    pub marketplace_fee: u64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub recipient: Pubkey,
+    pub protocol_amount: u64,
+    pub marketplace_amount: u64,
+}
+
+#[event]
+pub struct OfferPlaced {
+    pub round: u64,
+    pub buyer: Pubkey,
+    pub nonce: u64,
+    pub target: OfferTarget,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct OfferCancelled {
+    pub buyer: Pubkey,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct OfferAccepted {
+    pub ticket_id: u64,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub nonce: u64,
+    pub price: u64,
 }
 
 #[event]
@@ -372,6 +1803,21 @@ pub struct TicketMinted {
     pub ticket_id: u64,
     pub owner: Pubkey,
     pub numbers: [u8; 6],
+    pub price: u64,
+    pub reveal_index: u8,
+}
+
+#[event]
+pub struct OracleConfigured {
+    pub oracle_program: Pubkey,
+    pub oracle_signer: Pubkey,
+}
+
+#[event]
+pub struct RevealRequested {
+    pub round: u64,
+    pub reveal_index: u8,
+    pub seed: [u8; 32],
 }
 
 #[event]
@@ -387,11 +1833,91 @@ pub struct PrizeAwarded {
     pub amount: u64,
 }
 
+#[event]
+pub struct WinnerRegistered {
+    pub round: u64,
+    pub ticket_id: u64,
+    pub matches: u8,
+}
+
+#[event]
+pub struct RoundSettled {
+    pub round: u64,
+    pub winner_counts: [u32; 7],
+}
+
+#[event]
+pub struct RoundCancelled {
+    pub round: u64,
+}
+
+#[event]
+pub struct TicketRefunded {
+    pub ticket_id: u64,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
 #[event]
 pub struct NewRoundStarted {
     pub round: u64,
 }
 
+#[event]
+pub struct RoleGranted {
+    pub role: Role,
+    pub account: Pubkey,
+}
+
+#[event]
+pub struct RoleRevoked {
+    pub role: Role,
+    pub account: Pubkey,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub paused: u8,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct ApproversUpdated {
+    pub approvers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub approvers_epoch: u64,
+}
+
+#[event]
+pub struct ProposalCreated {
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+}
+
+#[event]
+pub struct ProposalApproved {
+    pub proposal_id: u64,
+    pub approver: Pubkey,
+    pub approvals: u8,
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub proposal_id: u64,
+}
+
 #[event]
 pub struct TicketListed {
     pub ticket_id: u64,
@@ -415,7 +1941,7 @@ pub struct TicketSold {
 
 #[error_code]
 pub enum ErrorCode {
-    #[msg("Invalid number range, must be between 0 and 99")]
+    #[msg("Number is outside the lottery's range")]
     InvalidNumberRange,
     #[msg("This number sequence already exists")]
     DuplicateSequence,
@@ -443,4 +1969,495 @@ pub enum ErrorCode {
     CannotBuyOwnTicket,
     #[msg("Not the seller")]
     NotSeller,
-}
\ No newline at end of file
+    #[msg("Prize tiers must pay nothing for 0 matches and total at most 100%")]
+    InvalidPrizeTiers,
+    #[msg("Prize pool can't cover this payout")]
+    InsufficientPrizePool,
+    #[msg("Round already settled")]
+    RoundAlreadySettled,
+    #[msg("Round not settled")]
+    RoundNotSettled,
+    #[msg("Settlement period has ended")]
+    SettlementClosed,
+    #[msg("Settlement period has not ended yet")]
+    SettlementStillOpen,
+    #[msg("Ticket already registered")]
+    AlreadyRegistered,
+    #[msg("Ticket was not registered as a winner")]
+    TicketNotRegistered,
+    #[msg("Round is cancelled")]
+    RoundCancelled,
+    #[msg("Round not cancelled")]
+    RoundNotCancelled,
+    #[msg("Reveals haven't stalled long enough to cancel")]
+    RevealsNotStalled,
+    #[msg("Ticket already refunded")]
+    AlreadyRefunded,
+    #[msg("Sales close index must be between 1 and 6")]
+    InvalidSalesCloseIndex,
+    #[msg("Ticket sales are closed for this round")]
+    SalesClosed,
+    #[msg("Price multipliers must start at 100% and never decrease")]
+    InvalidPriceMultipliers,
+    #[msg("Signer doesn't hold the required role")]
+    Unauthorized,
+    #[msg("The admin role can't be revoked")]
+    CannotRevokeAdmin,
+    #[msg("Role can't be granted to the default pubkey")]
+    InvalidRoleAccount,
+    #[msg("The admin role moves with propose_authority and accept_authority")]
+    AdminNeedsTwoStepTransfer,
+    #[msg("No admin transfer is pending")]
+    NoPendingAdmin,
+    #[msg("This operation is paused")]
+    Paused,
+    #[msg("Approvers must be distinct, at most 8, with a threshold no larger than the set")]
+    InvalidApprovers,
+    #[msg("The multisig is off")]
+    MultisigDisabled,
+    #[msg("Signer is not an approver")]
+    NotApprover,
+    #[msg("Approver already approved this proposal")]
+    AlreadyApproved,
+    #[msg("The multisig is on; this operation needs an approved proposal")]
+    ProposalRequired,
+    #[msg("Proposal is for a different operation")]
+    ProposalMismatch,
+    #[msg("Proposal already executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal was approved by a previous approver set")]
+    StaleProposal,
+    #[msg("Proposal doesn't have enough approvals yet")]
+    ThresholdNotMet,
+    #[msg("Invalid number range")]
+    InvalidNumberBounds,
+    #[msg("Numbers may not repeat within a ticket")]
+    RepeatedNumber,
+    #[msg("Config values are out of bounds")]
+    InvalidConfig,
+    #[msg("Not enough accrued fees to withdraw")]
+    InsufficientFees,
+    #[msg("Ticket is listed on the marketplace; delist it first")]
+    TicketIsListed,
+    #[msg("Listing expired when the ticket's round ended")]
+    ListingExpired,
+    #[msg("Seller no longer owns this ticket")]
+    SellerNotOwner,
+    #[msg("Offers need a future expiry and a prefix of numbers already revealed")]
+    InvalidOffer,
+    #[msg("Offer has expired")]
+    OfferExpired,
+    #[msg("Ticket doesn't match the offer")]
+    OfferMismatch,
+    #[msg("Only the buyer can cancel an offer that can still be accepted")]
+    OfferStillActive,
+    #[msg("Reveals for this lottery go through a different randomness source")]
+    WrongRandomnessSource,
+    #[msg("Randomness not from the configured oracle")]
+    UnauthorizedOracle,
+    #[msg("Invalid oracle proof")]
+    InvalidOracleProof,
+    #[msg("Reveal request already fulfilled")]
+    RequestAlreadyFulfilled,
+    #[msg("Can't change the randomness source once the round has started")]
+    RoundAlreadyStarted,
+}
+
+fn valid_prize_tiers(prize_tiers: &[u16; 7]) -> bool {
+    let total: u32 = prize_tiers.iter().map(|&bps| bps as u32).sum();
+    prize_tiers[0] == 0 && total > 0 && total <= 10000
+}
+
+fn valid_price_multipliers(price_multipliers: &[u32; 6], sales_close_index: u8) -> bool {
+    let on_sale = &price_multipliers[..sales_close_index as usize];
+    on_sale[0] == 10000 && on_sale.windows(2).all(|pair| pair[0] <= pair[1])
+}
+
+// Lets a privileged operation through for the role holder while the multisig
+// is off, or for a matching proposal that has reached the threshold while it's
+// on. The proposal is marked executed so it can't be used twice.
+fn authorize(
+    config: &Config,
+    role_holder: Pubkey,
+    signer: Pubkey,
+    proposal: &mut Option<Account<Proposal>>,
+    action: ProposalAction,
+) -> Result<()> {
+    if config.threshold == 0 {
+        require!(signer == role_holder, ErrorCode::Unauthorized);
+        return Ok(());
+    }
+
+    let proposal = proposal.as_mut().ok_or(ErrorCode::ProposalRequired)?;
+    require!(proposal.action == action, ErrorCode::ProposalMismatch);
+    require!(!proposal.executed, ErrorCode::ProposalAlreadyExecuted);
+    require!(
+        proposal.approvers_epoch == config.approvers_epoch,
+        ErrorCode::StaleProposal
+    );
+    require!(
+        proposal.approvals.count_ones() >= config.threshold as u32,
+        ErrorCode::ThresholdNotMet
+    );
+    proposal.executed = true;
+
+    emit!(ProposalExecuted {
+        proposal_id: proposal.id,
+    });
+
+    Ok(())
+}
+
+fn authorize_admin(ctx: &mut Context<UpdateConfig>, action: ProposalAction) -> Result<()> {
+    let config = &ctx.accounts.config;
+    authorize(
+        config,
+        config.admin,
+        ctx.accounts.admin.key(),
+        &mut ctx.accounts.proposal,
+        action,
+    )
+}
+
+fn approver_index(config: &Config, approver: &Pubkey) -> Result<usize> {
+    config.approvers[..config.approver_count as usize]
+        .iter()
+        .position(|key| key == approver)
+        .ok_or_else(|| error!(ErrorCode::NotApprover))
+}
+
+// Owner and marketplace fees on a secondary sale of `price` lamports
+fn sale_fees(config: &Config, price: u64) -> (u64, u64) {
+    let owner_fee = price.checked_mul(config.owner_fee_bps as u64).unwrap() / 10000;
+    let marketplace_fee = price.checked_mul(config.marketplace_fee_bps as u64).unwrap() / 10000;
+    (owner_fee, marketplace_fee)
+}
+
+fn offer_matches(target: &OfferTarget, ticket: &Ticket, lottery: &Lottery) -> bool {
+    match target {
+        OfferTarget::Ticket { ticket_id } => ticket.ticket_id == *ticket_id,
+        OfferTarget::Prefix { length } => {
+            if *length > lottery.current_reveal_index {
+                return false;
+            }
+            let revealed = &lottery.revealed_numbers[..*length as usize];
+            matches_revealed(&ticket.numbers, revealed, lottery.match_mode.into())
+        }
+    }
+}
+
+fn check_reveal_ready(lottery: &Lottery, config: &Config) -> Result<()> {
+    require!(!lottery.round_complete, ErrorCode::RoundComplete);
+    require!(!lottery.round_cancelled, ErrorCode::RoundCancelled);
+    require!(
+        Clock::get()?.unix_timestamp >= lottery.last_reveal_time + config.reveal_interval,
+        ErrorCode::TooEarlyForReveal
+    );
+    Ok(())
+}
+
+// Stores a freshly drawn number in the lottery and its round archive, closing
+// the round once all six are out
+fn record_reveal(lottery: &mut Lottery, round_result: &mut RoundResult, number: u8) -> Result<()> {
+    let reveal_index = lottery.current_reveal_index;
+    lottery.revealed_numbers[reveal_index as usize] = number;
+    round_result.revealed_numbers[reveal_index as usize] = number;
+
+    emit!(NumberRevealed {
+        round: lottery.current_round,
+        number,
+        reveal_index,
+    });
+
+    lottery.current_reveal_index += 1;
+    lottery.last_reveal_time = Clock::get()?.unix_timestamp;
+
+    if lottery.current_reveal_index == 6 {
+        lottery.round_complete = true;
+        lottery.round_settled = false;
+        // A complete round can't be cancelled, so its mint fees are earned
+        lottery.round_mint_fees = 0;
+        // Tiers pay out of the pool as it stood when the round closed, less
+        // what earlier rounds still owe
+        round_result.prize_pool = lottery
+            .accumulated_prize
+            .checked_sub(lottery.outstanding_liabilities)
+            .ok_or(ErrorCode::InsufficientPrizePool)?;
+        round_result.settlement_deadline = lottery.last_reveal_time + SETTLEMENT_PERIOD;
+    }
+
+    Ok(())
+}
+
+// What each registered winner with `matches` matches is owed; zero for tiers
+// without winners
+fn winner_share(round_result: &RoundResult, matches: usize) -> u64 {
+    match round_result.winner_counts[matches] {
+        0 => 0,
+        winners => {
+            let tier_pool = (round_result.prize_pool as u128
+                * round_result.prize_tiers[matches] as u128
+                / 10000) as u64;
+            tier_pool / winners as u64
+        }
+    }
+}
+
+// Tickets trade only while their round is still revealing numbers
+fn listing_open(lottery: &Lottery, ticket: &Ticket) -> bool {
+    ticket.round == lottery.current_round && !lottery.round_complete && !lottery.round_cancelled
+}
+
+fn open_round_result(round_result: &mut RoundResult, lottery: &Account<Lottery>, bump: u8) {
+    round_result.lottery = lottery.key();
+    round_result.round = lottery.current_round;
+    round_result.revealed_numbers = [0; 6];
+    round_result.prize_tiers = lottery.prize_tiers;
+    round_result.match_mode = lottery.match_mode;
+    round_result.prize_pool = 0;
+    round_result.winner_counts = [0; 7];
+    round_result.settlement_deadline = 0;
+    round_result.settled = false;
+    round_result.cancelled = false;
+    round_result.bump = bump;
+}
+
+// Picks the next number from min_number..=max_number. Without repeats it only
+// picks among numbers not revealed yet this round.
+fn draw_number(lottery: &Lottery, entropy: u64) -> u8 {
+    let revealed = &lottery.revealed_numbers[..lottery.current_reveal_index as usize];
+    let range = (lottery.max_number - lottery.min_number) as u64 + 1;
+    let available_count = if lottery.allow_repeats {
+        range
+    } else {
+        range - revealed.len() as u64
+    };
+
+    (lottery.min_number..=lottery.max_number)
+        .filter(|number| lottery.allow_repeats || !revealed.contains(number))
+        .nth((entropy % available_count) as usize)
+        .unwrap_or(lottery.min_number)
+}
+
+// Folds a slot hash or oracle randomness into the u64 draw_number takes
+fn entropy_from(bytes: &[u8]) -> u64 {
+    let digest = hash(bytes).to_bytes();
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+fn has_repeats(numbers: &[u8]) -> bool {
+    numbers
+        .iter()
+        .enumerate()
+        .any(|(i, number)| numbers[..i].contains(number))
+}
+
+// The one place a ticket is scored against a round's numbers
+fn count_matches(numbers: &[u8; 6], revealed_numbers: &[u8; 6], match_mode: MatchMode) -> usize {
+    match_rules::count_matches(numbers, revealed_numbers, match_mode.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blank<T: AnchorDeserialize>() -> T {
+        T::deserialize(&mut &[0u8; 1024][..]).unwrap()
+    }
+
+    // Reveals all six numbers the way record_reveal does
+    fn draw_all(lottery: &mut Lottery, entropy: u64) {
+        for index in 0..6u64 {
+            let number = draw_number(lottery, entropy_from(&(entropy + index).to_le_bytes()));
+            lottery.revealed_numbers[lottery.current_reveal_index as usize] = number;
+            lottery.current_reveal_index += 1;
+        }
+    }
+
+    #[test]
+    fn draw_without_repeats_uses_each_number_once() {
+        // A range exactly six wide has to come out as a permutation
+        for entropy in 0..50 {
+            let mut lottery: Lottery = blank();
+            lottery.min_number = 10;
+            lottery.max_number = 15;
+            draw_all(&mut lottery, entropy * 100);
+
+            let mut drawn = lottery.revealed_numbers.to_vec();
+            drawn.sort();
+            assert_eq!(drawn, vec![10, 11, 12, 13, 14, 15]);
+        }
+    }
+
+    #[test]
+    fn draw_stays_in_range() {
+        for allow_repeats in [false, true] {
+            let mut lottery: Lottery = blank();
+            lottery.min_number = 1;
+            lottery.max_number = 49;
+            lottery.allow_repeats = allow_repeats;
+            draw_all(&mut lottery, 3);
+
+            assert!(lottery.revealed_numbers.iter().all(|n| (1..=49).contains(n)));
+            if !allow_repeats {
+                assert!(!has_repeats(&lottery.revealed_numbers));
+            }
+        }
+    }
+
+    #[test]
+    fn prize_tiers_validation() {
+        assert!(valid_prize_tiers(&[0, 0, 0, 500, 1500, 3000, 5000]));
+        // Paying for zero matches
+        assert!(!valid_prize_tiers(&[1, 0, 0, 0, 0, 0, 5000]));
+        // Over 100%
+        assert!(!valid_prize_tiers(&[0, 0, 0, 0, 0, 5000, 5001]));
+        assert!(!valid_prize_tiers(&[0; 7]));
+    }
+
+    #[test]
+    fn price_multipliers_validation() {
+        assert!(valid_price_multipliers(&[10000, 12000, 15000, 1, 1, 1], 3));
+        // Entries past the close index are never charged, so they aren't checked
+        assert!(!valid_price_multipliers(&[10000, 12000, 15000, 1, 1, 1], 4));
+        assert!(!valid_price_multipliers(&[9000, 12000, 15000, 0, 0, 0], 3));
+        assert!(!valid_price_multipliers(&[10000, 12000, 11000, 0, 0, 0], 3));
+    }
+
+    // Runs authorize for the admin role with `proposal` passed in, returning
+    // whether the proposal came out marked executed
+    fn authorize_with(
+        config: &Config,
+        signer: Pubkey,
+        proposal: Option<&Proposal>,
+        action: ProposalAction,
+    ) -> Result<bool> {
+        let key = Pubkey::new_unique();
+        let owner = crate::ID;
+        let mut lamports = 0;
+        let mut data = Vec::new();
+        if let Some(proposal) = proposal {
+            proposal.try_serialize(&mut data).unwrap();
+        }
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        let mut proposal = proposal.map(|_| Account::<Proposal>::try_from(&info).unwrap());
+
+        authorize(config, config.admin, signer, &mut proposal, action)?;
+        Ok(proposal.is_some_and(|proposal| proposal.executed))
+    }
+
+    // A multisig needing two approvals and a proposal to cancel round 0 with `approvals`
+    fn multisig(approvals: u8) -> (Config, Proposal) {
+        let mut config: Config = blank();
+        config.admin = Pubkey::new_unique();
+        config.threshold = 2;
+        config.approvers_epoch = 1;
+
+        let mut proposal: Proposal = blank();
+        proposal.action = ProposalAction::CancelRound { round: 0 };
+        proposal.approvals = approvals;
+        proposal.approvers_epoch = 1;
+        (config, proposal)
+    }
+
+    const CANCEL: ProposalAction = ProposalAction::CancelRound { round: 0 };
+
+    #[test]
+    fn without_multisig_only_the_role_holder_is_authorized() {
+        let mut config: Config = blank();
+        config.admin = Pubkey::new_unique();
+        assert_eq!(authorize_with(&config, config.admin, None, CANCEL), Ok(false));
+        assert_eq!(
+            authorize_with(&config, Pubkey::new_unique(), None, CANCEL),
+            Err(ErrorCode::Unauthorized.into())
+        );
+    }
+
+    #[test]
+    fn multisig_needs_a_proposal_even_from_the_role_holder() {
+        let (config, _) = multisig(0b011);
+        assert_eq!(
+            authorize_with(&config, config.admin, None, CANCEL),
+            Err(ErrorCode::ProposalRequired.into())
+        );
+    }
+
+    #[test]
+    fn multisig_executes_an_approved_proposal_once() {
+        let (config, mut proposal) = multisig(0b011);
+        let anyone = Pubkey::new_unique();
+        assert_eq!(authorize_with(&config, anyone, Some(&proposal), CANCEL), Ok(true));
+
+        proposal.executed = true;
+        assert_eq!(
+            authorize_with(&config, anyone, Some(&proposal), CANCEL),
+            Err(ErrorCode::ProposalAlreadyExecuted.into())
+        );
+    }
+
+    #[test]
+    fn multisig_rejects_proposals_for_other_actions() {
+        let (config, proposal) = multisig(0b011);
+        assert_eq!(
+            authorize_with(
+                &config,
+                config.admin,
+                Some(&proposal),
+                ProposalAction::CancelRound { round: 1 }
+            ),
+            Err(ErrorCode::ProposalMismatch.into())
+        );
+    }
+
+    #[test]
+    fn multisig_rejects_proposals_short_of_the_threshold() {
+        let (config, proposal) = multisig(0b100);
+        assert_eq!(
+            authorize_with(&config, config.admin, Some(&proposal), CANCEL),
+            Err(ErrorCode::ThresholdNotMet.into())
+        );
+    }
+
+    #[test]
+    fn multisig_rejects_approvals_from_a_replaced_approver_set() {
+        let (mut config, proposal) = multisig(0b011);
+        config.approvers_epoch = 2;
+        assert_eq!(
+            authorize_with(&config, config.admin, Some(&proposal), CANCEL),
+            Err(ErrorCode::StaleProposal.into())
+        );
+    }
+
+    #[test]
+    fn winners_split_their_tier_equally() {
+        let mut round_result: RoundResult = blank();
+        round_result.prize_pool = 1000;
+        round_result.prize_tiers = [0, 0, 0, 3000, 0, 0, 7000];
+
+        assert_eq!(winner_share(&round_result, 6), 0);
+        round_result.winner_counts[3] = 7;
+        assert_eq!(winner_share(&round_result, 3), 42);
+        round_result.winner_counts[6] = 1;
+        assert_eq!(winner_share(&round_result, 6), 700);
+    }
+
+    #[test]
+    fn offer_matches_ticket_id_or_prefix() {
+        let mut ticket: Ticket = blank();
+        ticket.ticket_id = 7;
+        ticket.numbers = [1, 2, 3, 4, 5, 6];
+
+        let mut lottery: Lottery = blank();
+        lottery.revealed_numbers = [1, 2, 9, 0, 0, 0];
+        lottery.current_reveal_index = 3;
+
+        assert!(offer_matches(&OfferTarget::Ticket { ticket_id: 7 }, &ticket, &lottery));
+        assert!(!offer_matches(&OfferTarget::Ticket { ticket_id: 8 }, &ticket, &lottery));
+
+        let prefix = |length| OfferTarget::Prefix { length };
+        assert!(offer_matches(&prefix(2), &ticket, &lottery));
+        assert!(!offer_matches(&prefix(3), &ticket, &lottery));
+        // Nothing past the last reveal can match yet
+        assert!(!offer_matches(&prefix(4), &ticket, &lottery));
+    }
+}
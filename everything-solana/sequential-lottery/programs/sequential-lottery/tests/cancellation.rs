@@ -0,0 +1,87 @@
+//! Cancelling a round and refunding its tickets, owner fee included.
+
+mod common;
+
+use common::{error, params, Env, PRICE};
+use sequential_lottery::{ErrorCode, RoundResult, Ticket};
+
+#[test]
+fn admin_cancels_and_tickets_are_refunded_in_full_once() {
+    let mut env = Env::new(params());
+    let mut tickets = vec![];
+    for numbers in [[1, 2, 3, 4, 5, 6], [7, 8, 9, 10, 11, 12]] {
+        let player = env.bank.new_user();
+        tickets.push((player, env.mint_ticket(player, numbers).unwrap()));
+    }
+    let (player, ticket) = tickets[0];
+    assert_eq!(env.refund(player, ticket), Err(error(ErrorCode::RoundNotCancelled)));
+
+    let admin = env.admin;
+    env.cancel(admin).unwrap();
+    assert!(env.bank.get::<RoundResult>(&env.round_result(0)).cancelled);
+    assert_eq!(
+        env.mint_ticket(player, [2, 3, 4, 5, 6, 7]),
+        Err(error(ErrorCode::RoundCancelled))
+    );
+
+    // The round's owner fees go back into the pool the refunds come from
+    let lottery = env.lottery();
+    assert_eq!(lottery.protocol_fees_accrued, 0);
+    assert_eq!(lottery.accumulated_prize, 2 * PRICE);
+    assert_eq!(lottery.outstanding_liabilities, 2 * PRICE);
+
+    let stranger = env.bank.new_user();
+    assert_eq!(env.refund(stranger, ticket), Err(error(ErrorCode::NotTicketOwner)));
+
+    // Refunds still go through once the next round is under way
+    env.start_new_round().unwrap();
+    for (player, ticket) in tickets {
+        let before = env.bank.lamports(&player);
+        env.refund(player, ticket).unwrap();
+        assert_eq!(env.bank.lamports(&player) - before, PRICE);
+        assert!(env.bank.get::<Ticket>(&ticket).refunded);
+        assert_eq!(env.refund(player, ticket), Err(error(ErrorCode::AlreadyRefunded)));
+    }
+    let lottery = env.lottery();
+    assert_eq!(lottery.accumulated_prize, 0);
+    assert_eq!(lottery.outstanding_liabilities, 0);
+}
+
+#[test]
+fn anyone_cancels_once_reveals_stall() {
+    let mut env = Env::new(params());
+    let player = env.bank.new_user();
+    env.mint_ticket(player, [1, 2, 3, 4, 5, 6]).unwrap();
+    env.reveal_number().unwrap();
+
+    // Three reveal intervals after the last reveal
+    let stranger = env.bank.new_user();
+    let stalled_at = env.lottery().last_reveal_time + 3 * env.config().reveal_interval;
+    env.bank.warp_to(stalled_at - 1);
+    assert_eq!(env.cancel(stranger), Err(error(ErrorCode::Unauthorized)));
+    env.bank.warp_to(stalled_at);
+    env.cancel(stranger).unwrap();
+    assert!(env.lottery().round_cancelled);
+}
+
+#[test]
+fn a_complete_round_cannot_be_cancelled() {
+    let mut env = Env::new(params());
+    env.reveal_all();
+    let admin = env.admin;
+    assert_eq!(env.cancel(admin), Err(error(ErrorCode::RoundComplete)));
+}
+
+#[test]
+fn listed_tickets_are_refunded() {
+    let mut env = Env::new(params());
+    let player = env.bank.new_user();
+    let ticket = env.mint_ticket(player, [1, 2, 3, 4, 5, 6]).unwrap();
+    env.list(player, ticket, 2 * PRICE).unwrap();
+
+    let admin = env.admin;
+    env.cancel(admin).unwrap();
+    let before = env.bank.lamports(&player);
+    env.refund(player, ticket).unwrap();
+    assert_eq!(env.bank.lamports(&player) - before, PRICE);
+}
@@ -0,0 +1,410 @@
+//! Shared setup for the instruction tests: a bank with the lottery deployed
+//! and initialized, and the PDAs and instructions the tests keep needing.
+
+#![allow(dead_code)] // Not every test file uses every helper
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use program_harness::{programdata_address, Bank};
+use sequential_lottery::{accounts, instruction, ConfigParams, InitializeParams, MatchMode, ProposalAction};
+
+pub struct Env {
+    pub bank: Bank,
+    pub admin: Pubkey,
+    pub lottery: Pubkey,
+    pub config: Pubkey,
+}
+
+// A ticket bought before the first reveal: the default mint price, 1% of it
+// the owner fee
+pub const PRICE: u64 = 10_000_000;
+pub const OWNER_FEE: u64 = PRICE / 100;
+
+pub fn params() -> InitializeParams {
+    InitializeParams {
+        prize_tiers: [0, 0, 0, 1000, 2000, 3000, 4000],
+        sales_close_index: 3,
+        price_multipliers: [10000, 15000, 20000, 20000, 20000, 20000],
+        min_number: 1,
+        max_number: 49,
+        allow_repeats: false,
+        match_mode: MatchMode::OrderedExact,
+    }
+}
+
+impl Env {
+    /// The lottery deployed and initialized by its upgrade authority, who
+    /// holds every role.
+    pub fn new(params: InitializeParams) -> Self {
+        let mut bank = Bank::new();
+        bank.add_program(sequential_lottery::ID, sequential_lottery::entry);
+        bank.add_program(mock_oracle::ID, mock_oracle::entry);
+        let admin = bank.new_user();
+        bank.set_upgrade_authority(&sequential_lottery::ID, Some(admin));
+
+        let lottery = pda(&[b"lottery"]);
+        let config = pda(&[b"config"]);
+        let mut env = Self {
+            bank,
+            admin,
+            lottery,
+            config,
+        };
+        let accounts = accounts::Initialize {
+            lottery,
+            round_result: env.round_result(0),
+            config,
+            user: admin,
+            program: sequential_lottery::ID,
+            program_data: programdata_address(&sequential_lottery::ID),
+            system_program: system_program::ID,
+        };
+        env.send(accounts, instruction::Initialize { params }, &[admin])
+            .expect("lottery initializes");
+        env
+    }
+
+    pub fn send(
+        &mut self,
+        accounts: impl ToAccountMetas,
+        data: impl InstructionData,
+        signers: &[Pubkey],
+    ) -> std::result::Result<(), ProgramError> {
+        self.bank.process(&[ix(accounts, data)], signers)
+    }
+
+    pub fn round_result(&self, round: u64) -> Pubkey {
+        pda(&[b"round", self.lottery.as_ref(), &round.to_le_bytes()])
+    }
+
+    pub fn ticket(&self, round: u64, ticket_id: u64) -> Pubkey {
+        pda(&[
+            b"ticket",
+            self.lottery.as_ref(),
+            &round.to_le_bytes(),
+            &ticket_id.to_le_bytes(),
+        ])
+    }
+
+    /// The sequence claim for `numbers`, derived the way clients are told to:
+    /// sha256 of the numbers, sorted and deduplicated when they play as a set.
+    pub fn sequence_claim(&self, round: u64, numbers: &[u8; 6]) -> Pubkey {
+        let mut key = numbers.to_vec();
+        if self.lottery().match_mode == MatchMode::UnorderedSet {
+            key.sort();
+            key.dedup();
+        }
+        pda(&[
+            b"sequence",
+            self.lottery.as_ref(),
+            &round.to_le_bytes(),
+            hash(&key).as_ref(),
+        ])
+    }
+
+    pub fn reveal_request(&self, round: u64, reveal_index: u8) -> Pubkey {
+        pda(&[
+            b"reveal_request",
+            self.lottery.as_ref(),
+            &round.to_le_bytes(),
+            &[reveal_index],
+        ])
+    }
+
+    pub fn lottery(&self) -> sequential_lottery::Lottery {
+        self.bank.get(&self.lottery)
+    }
+
+    pub fn config(&self) -> sequential_lottery::Config {
+        self.bank.get(&self.config)
+    }
+
+    /// Buys `numbers` for `user` in the current round, returning the ticket.
+    pub fn mint_ticket(
+        &mut self,
+        user: Pubkey,
+        numbers: [u8; 6],
+    ) -> std::result::Result<Pubkey, ProgramError> {
+        let lottery = self.lottery();
+        let ticket = self.ticket(lottery.current_round, lottery.next_ticket_id);
+        let accounts = accounts::MintTicket {
+            lottery: self.lottery,
+            config: self.config,
+            ticket,
+            sequence_claim: self.sequence_claim(lottery.current_round, &numbers),
+            user,
+            system_program: system_program::ID,
+            slot_hashes: sysvar::slot_hashes::ID,
+        };
+        self.send(accounts, instruction::MintTicket { numbers }, &[user])?;
+        Ok(ticket)
+    }
+
+    /// Moves the clock far enough on for the next reveal.
+    pub fn wait_for_reveal(&mut self) {
+        let ready_at = self.lottery().last_reveal_time + self.config().reveal_interval;
+        self.bank.warp_to(ready_at);
+    }
+
+    /// Reveals the next number from the slot hash, as the revealer.
+    pub fn reveal_number(&mut self) -> std::result::Result<(), ProgramError> {
+        self.wait_for_reveal();
+        let lottery = self.lottery();
+        let accounts = accounts::RevealNumber {
+            lottery: self.lottery,
+            round_result: self.round_result(lottery.current_round),
+            config: self.config,
+            revealer: self.admin,
+            proposal: None,
+            slot_hashes: sysvar::slot_hashes::ID,
+        };
+        let admin = self.admin;
+        self.send(accounts, instruction::RevealNumber {}, &[admin])
+    }
+
+    /// Reveals every remaining number, completing the round, and returns them.
+    pub fn reveal_all(&mut self) -> [u8; 6] {
+        while !self.lottery().round_complete {
+            self.reveal_number().unwrap();
+        }
+        self.lottery().revealed_numbers
+    }
+
+    pub fn register(&mut self, ticket: Pubkey) -> std::result::Result<(), ProgramError> {
+        let round = self.bank.get::<sequential_lottery::Ticket>(&ticket).round;
+        let accounts = accounts::RegisterWinner {
+            lottery: self.lottery,
+            ticket,
+            round_result: self.round_result(round),
+        };
+        self.send(accounts, instruction::RegisterWinner {}, &[])
+    }
+
+    /// Waits out the settlement window and settles the current round.
+    pub fn finalize(&mut self) -> std::result::Result<(), ProgramError> {
+        let round_result = self.round_result(self.lottery().current_round);
+        let deadline = self
+            .bank
+            .get::<sequential_lottery::RoundResult>(&round_result)
+            .settlement_deadline;
+        self.bank.warp_to(deadline);
+        let accounts = accounts::FinalizeSettlement {
+            lottery: self.lottery,
+            round_result,
+        };
+        self.send(accounts, instruction::FinalizeSettlement {}, &[])
+    }
+
+    pub fn claim(&mut self, user: Pubkey, ticket: Pubkey) -> std::result::Result<(), ProgramError> {
+        let round = self.bank.get::<sequential_lottery::Ticket>(&ticket).round;
+        let accounts = accounts::ClaimPrize {
+            lottery: self.lottery,
+            config: self.config,
+            ticket,
+            round_result: self.round_result(round),
+            user,
+        };
+        self.send(accounts, instruction::ClaimPrize {}, &[user])
+    }
+
+    pub fn cancel(&mut self, caller: Pubkey) -> std::result::Result<(), ProgramError> {
+        self.cancel_with(caller, None)
+    }
+
+    /// Cancels the current round as `caller`, executing `proposal` if there
+    /// is one.
+    pub fn cancel_with(
+        &mut self,
+        caller: Pubkey,
+        proposal: Option<Pubkey>,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::CancelRound {
+            lottery: self.lottery,
+            round_result: self.round_result(self.lottery().current_round),
+            config: self.config,
+            caller,
+            proposal,
+        };
+        self.send(accounts, instruction::CancelRound {}, &[caller])
+    }
+
+    pub fn refund(&mut self, user: Pubkey, ticket: Pubkey) -> std::result::Result<(), ProgramError> {
+        let round = self.bank.get::<sequential_lottery::Ticket>(&ticket).round;
+        let accounts = accounts::RefundTicket {
+            lottery: self.lottery,
+            ticket,
+            round_result: self.round_result(round),
+            user,
+        };
+        self.send(accounts, instruction::RefundTicket {}, &[user])
+    }
+
+    pub fn listing(&self, ticket: Pubkey) -> Pubkey {
+        let ticket = self.bank.get::<sequential_lottery::Ticket>(&ticket);
+        pda(&[
+            b"listing",
+            self.lottery.as_ref(),
+            &ticket.round.to_le_bytes(),
+            &ticket.ticket_id.to_le_bytes(),
+        ])
+    }
+
+    pub fn list(
+        &mut self,
+        user: Pubkey,
+        ticket: Pubkey,
+        price: u64,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::ListTicket {
+            lottery: self.lottery,
+            config: self.config,
+            ticket,
+            listing: self.listing(ticket),
+            user,
+            system_program: system_program::ID,
+        };
+        self.send(accounts, instruction::ListTicket { price }, &[user])
+    }
+
+    pub fn delist(&mut self, user: Pubkey, ticket: Pubkey) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::DelistTicket {
+            listing: self.listing(ticket),
+            ticket,
+            user,
+        };
+        self.send(accounts, instruction::DelistTicket {}, &[user])
+    }
+
+    /// Buys a listed ticket from its seller.
+    pub fn buy(&mut self, user: Pubkey, ticket: Pubkey) -> std::result::Result<(), ProgramError> {
+        let listing = self.listing(ticket);
+        let seller = self.bank.get::<sequential_lottery::MarketplaceListing>(&listing).seller;
+        let accounts = accounts::BuyTicket {
+            lottery: self.lottery,
+            ticket,
+            listing,
+            user,
+            seller,
+            config: self.config,
+            system_program: system_program::ID,
+        };
+        self.send(accounts, instruction::BuyTicket {}, &[user])
+    }
+
+    pub fn withdraw_fees(&mut self, caller: Pubkey) -> std::result::Result<(), ProgramError> {
+        self.withdraw_fees_with(caller, None)
+    }
+
+    pub fn withdraw_fees_with(
+        &mut self,
+        caller: Pubkey,
+        proposal: Option<Pubkey>,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::WithdrawFees {
+            lottery: self.lottery,
+            config: self.config,
+            fee_collector: self.config().fee_collector,
+            caller,
+            proposal,
+        };
+        self.send(accounts, instruction::WithdrawFees {}, &[caller])
+    }
+
+    pub fn start_new_round(&mut self) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::StartNewRound {
+            lottery: self.lottery,
+            round_result: self.round_result(self.lottery().current_round + 1),
+            config: self.config,
+            admin: self.admin,
+            proposal: None,
+            system_program: system_program::ID,
+        };
+        let admin = self.admin;
+        self.send(accounts, instruction::StartNewRound {}, &[admin])
+    }
+
+    pub fn update_config(
+        &mut self,
+        caller: Pubkey,
+        params: ConfigParams,
+        proposal: Option<Pubkey>,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::UpdateConfig {
+            config: self.config,
+            admin: caller,
+            proposal,
+        };
+        self.send(accounts, instruction::UpdateConfig { params }, &[caller])
+    }
+
+    /// Turns the multisig on, as the admin while it's still off.
+    pub fn set_approvers(&mut self, approvers: Vec<Pubkey>, threshold: u8) {
+        let admin = self.admin;
+        let accounts = accounts::UpdateConfig {
+            config: self.config,
+            admin,
+            proposal: None,
+        };
+        let data = instruction::SetApprovers {
+            approvers,
+            threshold,
+        };
+        self.send(accounts, data, &[admin]).expect("approvers are set");
+    }
+
+    pub fn proposal(&self, id: u64) -> Pubkey {
+        pda(&[b"proposal", &id.to_le_bytes()])
+    }
+
+    /// Proposes `action` as `approver`, returning the proposal.
+    pub fn create_proposal(
+        &mut self,
+        approver: Pubkey,
+        action: ProposalAction,
+    ) -> std::result::Result<Pubkey, ProgramError> {
+        let proposal = self.proposal(self.config().proposal_count);
+        let accounts = accounts::CreateProposal {
+            config: self.config,
+            proposal,
+            approver,
+            system_program: system_program::ID,
+        };
+        self.send(accounts, instruction::CreateProposal { action }, &[approver])?;
+        Ok(proposal)
+    }
+
+    pub fn approve(&mut self, approver: Pubkey, proposal: Pubkey) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::ApproveProposal {
+            config: self.config,
+            proposal,
+            approver,
+        };
+        self.send(accounts, instruction::ApproveProposal {}, &[approver])
+    }
+}
+
+pub fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &sequential_lottery::ID).0
+}
+
+pub fn ix(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: sequential_lottery::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// What a failed `require!` with `error` comes back as.
+pub fn error(error: sequential_lottery::ErrorCode) -> ProgramError {
+    ProgramError::Custom(error.into())
+}
+
+/// What creating an account that already exists fails with: the system
+/// program's AccountAlreadyInUse.
+pub fn already_in_use() -> ProgramError {
+    ProgramError::Custom(0)
+}
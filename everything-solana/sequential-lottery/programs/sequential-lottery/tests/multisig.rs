@@ -0,0 +1,110 @@
+//! Privileged operations once the multisig is on: each needs a proposal for
+//! exactly that operation, approved by enough of the approvers.
+
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use common::{error, params, Env, OWNER_FEE};
+use sequential_lottery::{ConfigParams, ErrorCode, Proposal, ProposalAction};
+
+// Three approvers, any two of whom can act
+fn two_of_three(env: &mut Env) -> [Pubkey; 3] {
+    let approvers = [env.bank.new_user(), env.bank.new_user(), env.bank.new_user()];
+    env.set_approvers(approvers.to_vec(), 2);
+    approvers
+}
+
+#[test]
+fn cancel_round_needs_an_approved_proposal() {
+    let mut env = Env::new(params());
+    let [first, second, _] = two_of_three(&mut env);
+
+    let admin = env.admin;
+    assert_eq!(env.cancel(admin), Err(error(ErrorCode::ProposalRequired)));
+
+    let proposal = env
+        .create_proposal(first, ProposalAction::CancelRound { round: 0 })
+        .unwrap();
+    let stranger = env.bank.new_user();
+    assert_eq!(
+        env.cancel_with(stranger, Some(proposal)),
+        Err(error(ErrorCode::ThresholdNotMet))
+    );
+
+    env.approve(second, proposal).unwrap();
+    env.cancel_with(stranger, Some(proposal)).unwrap();
+    assert!(env.lottery().round_cancelled);
+    assert!(env.bank.get::<Proposal>(&proposal).executed);
+}
+
+#[test]
+fn update_config_applies_only_the_approved_params() {
+    let mut env = Env::new(params());
+    let [first, _, third] = two_of_three(&mut env);
+    let approved = ConfigParams {
+        mint_price: 20_000_000,
+        owner_fee_bps: 200,
+        marketplace_fee_bps: 300,
+        reveal_interval: 60 * 60,
+    };
+    let action = ProposalAction::UpdateConfig {
+        params_hash: hash(&approved.try_to_vec().unwrap()).to_bytes(),
+    };
+
+    let admin = env.admin;
+    assert_eq!(
+        env.update_config(admin, approved.clone(), None),
+        Err(error(ErrorCode::ProposalRequired))
+    );
+    let proposal = env.create_proposal(first, action).unwrap();
+    assert_eq!(
+        env.update_config(admin, approved.clone(), Some(proposal)),
+        Err(error(ErrorCode::ThresholdNotMet))
+    );
+    env.approve(third, proposal).unwrap();
+
+    // Any other params are a different operation
+    let mut other = approved.clone();
+    other.mint_price += 1;
+    assert_eq!(
+        env.update_config(admin, other, Some(proposal)),
+        Err(error(ErrorCode::ProposalMismatch))
+    );
+    env.update_config(admin, approved.clone(), Some(proposal)).unwrap();
+    let config = env.config();
+    assert_eq!(config.mint_price, approved.mint_price);
+    assert_eq!(config.owner_fee_bps, approved.owner_fee_bps);
+    assert_eq!(config.marketplace_fee_bps, approved.marketplace_fee_bps);
+    assert_eq!(config.reveal_interval, approved.reveal_interval);
+    assert_eq!(
+        env.update_config(admin, approved, Some(proposal)),
+        Err(error(ErrorCode::ProposalAlreadyExecuted))
+    );
+}
+
+#[test]
+fn withdraw_fees_needs_an_approved_proposal() {
+    // Earn a completed round's mint fees before the multisig goes on, since
+    // reveals need proposals after that too
+    let mut env = Env::new(params());
+    let player = env.bank.new_user();
+    env.mint_ticket(player, [1, 2, 3, 4, 5, 6]).unwrap();
+    env.reveal_all();
+    let [first, second, _] = two_of_three(&mut env);
+
+    let collector = env.config().fee_collector;
+    assert_eq!(env.withdraw_fees(collector), Err(error(ErrorCode::ProposalRequired)));
+    let proposal = env.create_proposal(second, ProposalAction::WithdrawFees).unwrap();
+    assert_eq!(
+        env.withdraw_fees_with(collector, Some(proposal)),
+        Err(error(ErrorCode::ThresholdNotMet))
+    );
+
+    env.approve(first, proposal).unwrap();
+    let before = env.bank.lamports(&collector);
+    let stranger = env.bank.new_user();
+    env.withdraw_fees_with(stranger, Some(proposal)).unwrap();
+    assert_eq!(env.bank.lamports(&collector) - before, OWNER_FEE);
+    assert_eq!(env.lottery().protocol_fees_accrued, 0);
+}
@@ -0,0 +1,167 @@
+//! The oracle reveal flow: request_reveal, then the mock oracle fulfilling it
+//! with an ed25519 proof over the request's seed. Without an oracle, reveals
+//! come from the slot hash.
+
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{system_program, sysvar};
+use common::{error, params, Env};
+use program_harness::Ed25519Signer;
+use sequential_lottery::{accounts, instruction, ErrorCode, RevealRequest};
+
+fn with_oracle(signer: &Ed25519Signer) -> Env {
+    let mut env = Env::new(params());
+    let accounts = accounts::ConfigureOracle {
+        lottery: env.lottery,
+        config: env.config,
+        admin: env.admin,
+        proposal: None,
+    };
+    let data = instruction::ConfigureOracle {
+        oracle_program: mock_oracle::ID,
+        oracle_signer: signer.pubkey(),
+    };
+    let admin = env.admin;
+    env.send(accounts, data, &[admin]).unwrap();
+    env
+}
+
+fn request_reveal(env: &mut Env) -> Pubkey {
+    env.wait_for_reveal();
+    let lottery = env.lottery();
+    let request = env.reveal_request(lottery.current_round, lottery.current_reveal_index);
+    let accounts = accounts::RequestReveal {
+        lottery: env.lottery,
+        config: env.config,
+        reveal_request: request,
+        revealer: env.admin,
+        proposal: None,
+        system_program: system_program::ID,
+    };
+    let admin = env.admin;
+    env.send(accounts, instruction::RequestReveal {}, &[admin]).unwrap();
+    request
+}
+
+fn fulfill(env: &Env, request: Pubkey, proof: [u8; 64]) -> Instruction {
+    let accounts = mock_oracle::accounts::Fulfill {
+        oracle_authority: Pubkey::find_program_address(
+            &[sequential_lottery::ORACLE_AUTHORITY_SEED],
+            &mock_oracle::ID,
+        )
+        .0,
+        lottery: env.lottery,
+        round_result: env.round_result(env.lottery().current_round),
+        config: env.config,
+        reveal_request: request,
+        instructions: sysvar::instructions::ID,
+        lottery_program: sequential_lottery::ID,
+    };
+    Instruction {
+        program_id: mock_oracle::ID,
+        accounts: accounts.to_account_metas(None),
+        data: mock_oracle::instruction::Fulfill { proof }.data(),
+    }
+}
+
+#[test]
+fn fulfilled_request_reveals_a_number() {
+    let oracle = Ed25519Signer::from_seed(7);
+    let mut env = with_oracle(&oracle);
+
+    for reveal_index in 0..6 {
+        let request = request_reveal(&mut env);
+        let seed = env.bank.get::<RevealRequest>(&request).seed;
+        let (proof, verify) = oracle.sign(&seed);
+        let fulfill = fulfill(&env, request, proof);
+        env.bank.process(&[verify, fulfill], &[]).unwrap();
+
+        let request: RevealRequest = env.bank.get(&request);
+        assert!(request.fulfilled);
+        assert_eq!(request.randomness, hash(&proof).to_bytes());
+
+        let lottery = env.lottery();
+        assert_eq!(lottery.current_reveal_index, reveal_index + 1);
+        let revealed = &lottery.revealed_numbers[..=reveal_index as usize];
+        assert!(revealed.iter().all(|number| (1..=49).contains(number)));
+    }
+
+    // Drawn without repeats, and archived with the round
+    let lottery = env.lottery();
+    assert!(lottery.round_complete);
+    let mut numbers = lottery.revealed_numbers;
+    numbers.sort();
+    assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+    let round_result: sequential_lottery::RoundResult = env.bank.get(&env.round_result(0));
+    assert_eq!(round_result.revealed_numbers, lottery.revealed_numbers);
+}
+
+#[test]
+fn forged_proof_is_rejected() {
+    let oracle = Ed25519Signer::from_seed(7);
+    let mut env = with_oracle(&oracle);
+    let request = request_reveal(&mut env);
+    let seed = env.bank.get::<RevealRequest>(&request).seed;
+
+    // Signed by some other key
+    let forger = Ed25519Signer::from_seed(8);
+    let (proof, verify) = forger.sign(&seed);
+    let result = env.bank.process(&[verify, fulfill(&env, request, proof)], &[]);
+    assert_eq!(result, Err(error(ErrorCode::InvalidOracleProof)));
+
+    // The oracle's signature, but over a different message
+    let (proof, verify) = oracle.sign(&[0; 32]);
+    let result = env.bank.process(&[verify, fulfill(&env, request, proof)], &[]);
+    assert_eq!(result, Err(error(ErrorCode::InvalidOracleProof)));
+
+    // The oracle's proof without the verify instruction in front of it
+    let (proof, _) = oracle.sign(&seed);
+    let result = env.bank.process(&[fulfill(&env, request, proof)], &[]);
+    assert_eq!(result, Err(error(ErrorCode::InvalidOracleProof)));
+
+    assert!(!env.bank.get::<RevealRequest>(&request).fulfilled);
+    assert_eq!(env.lottery().current_reveal_index, 0);
+
+    // The real proof still goes through afterwards
+    let (proof, verify) = oracle.sign(&seed);
+    env.bank.process(&[verify, fulfill(&env, request, proof)], &[]).unwrap();
+    assert_eq!(env.lottery().current_reveal_index, 1);
+}
+
+#[test]
+fn fulfill_needs_the_oracle_authority() {
+    let oracle = Ed25519Signer::from_seed(7);
+    let mut env = with_oracle(&oracle);
+    let request = request_reveal(&mut env);
+    let seed = env.bank.get::<RevealRequest>(&request).seed;
+    let (proof, verify) = oracle.sign(&seed);
+
+    // Straight to the lottery, signed by an account that isn't the oracle's PDA
+    let impostor = env.bank.new_user();
+    let accounts = accounts::FulfillReveal {
+        lottery: env.lottery,
+        round_result: env.round_result(0),
+        config: env.config,
+        reveal_request: request,
+        oracle_authority: impostor,
+        instructions: sysvar::instructions::ID,
+    };
+    let direct = common::ix(accounts, instruction::FulfillReveal { proof });
+    let result = env.bank.process(&[verify, direct], &[impostor]);
+    assert_eq!(result, Err(error(ErrorCode::UnauthorizedOracle)));
+}
+
+#[test]
+fn slot_hash_reveals_stop_once_an_oracle_is_configured() {
+    let mut env = Env::new(params());
+    env.reveal_number().unwrap();
+    assert_eq!(env.lottery().current_reveal_index, 1);
+
+    let oracle = Ed25519Signer::from_seed(7);
+    let mut env = with_oracle(&oracle);
+    assert_eq!(env.reveal_number(), Err(error(ErrorCode::WrongRandomnessSource)));
+}
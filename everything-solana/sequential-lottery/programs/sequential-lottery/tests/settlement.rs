@@ -0,0 +1,215 @@
+//! Registering winners, settling a round and paying its prize tiers, and
+//! withdrawing fees from the account that holds them.
+
+mod common;
+
+use common::{error, params, Env, OWNER_FEE, PRICE};
+use sequential_lottery::{ErrorCode, MatchMode, RoundResult, Ticket};
+
+// Six of 1..=7 in any order: the draw leaves out one number, so the ticket
+// leaving out the same one matches all six and the other six match five
+fn sets() -> Vec<[u8; 6]> {
+    (1..=7)
+        .map(|left_out| {
+            let mut numbers = [0; 6];
+            let picked = (1..=7).filter(|number| *number != left_out);
+            for (slot, number) in numbers.iter_mut().zip(picked) {
+                *slot = number;
+            }
+            numbers
+        })
+        .collect()
+}
+
+fn six_of_seven() -> Env {
+    let mut params = params();
+    params.max_number = 7;
+    params.match_mode = MatchMode::UnorderedSet;
+    Env::new(params)
+}
+
+fn matches(numbers: &[u8; 6], drawn: &[u8; 6]) -> usize {
+    numbers.iter().filter(|number| drawn.contains(number)).count()
+}
+
+#[test]
+fn claims_pay_the_tier_for_the_match_count() {
+    let mut env = six_of_seven();
+    let mut tickets = vec![];
+    for numbers in sets() {
+        let player = env.bank.new_user();
+        tickets.push((player, env.mint_ticket(player, numbers).unwrap(), numbers));
+    }
+
+    let drawn = env.reveal_all();
+    for (_, ticket, _) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+
+    // Tier 5 pays 30% of the pool and tier 6 40%
+    let pool = 7 * (PRICE - OWNER_FEE);
+    for (player, ticket, numbers) in tickets {
+        let before = env.bank.lamports(&player);
+        env.claim(player, ticket).unwrap();
+        let expected = match matches(&numbers, &drawn) {
+            6 => pool * 4000 / 10000,
+            _ => pool * 3000 / 10000 / 6,
+        };
+        assert_eq!(env.bank.lamports(&player) - before, expected);
+        assert!(env.bank.get::<Ticket>(&ticket).claimed);
+    }
+
+    let lottery = env.lottery();
+    assert_eq!(lottery.outstanding_liabilities, 0);
+    assert_eq!(lottery.accumulated_prize, pool - pool * 7000 / 10000);
+}
+
+#[test]
+fn claims_wait_for_settlement() {
+    let mut env = six_of_seven();
+    let player = env.bank.new_user();
+    let ticket = env.mint_ticket(player, sets()[0]).unwrap();
+
+    // Not while numbers are still being revealed, nor before it's settled
+    assert_eq!(env.register(ticket), Err(error(ErrorCode::RoundNotComplete)));
+    env.reveal_all();
+    env.register(ticket).unwrap();
+    assert_eq!(env.claim(player, ticket), Err(error(ErrorCode::RoundNotSettled)));
+    env.finalize().unwrap();
+    env.claim(player, ticket).unwrap();
+    assert!(env.bank.get::<RoundResult>(&env.round_result(0)).settled);
+}
+
+#[test]
+fn registered_winners_split_their_tier_and_the_rest_rolls_over() {
+    let mut env = six_of_seven();
+    let mut tickets = vec![];
+    for numbers in sets() {
+        let player = env.bank.new_user();
+        tickets.push((player, env.mint_ticket(player, numbers).unwrap(), numbers));
+    }
+    let pool = 7 * (PRICE - OWNER_FEE);
+
+    // One of the six five-match tickets never registers
+    let drawn = env.reveal_all();
+    let five_matches = tickets
+        .iter()
+        .position(|(.., numbers)| matches(numbers, &drawn) == 5)
+        .unwrap();
+    let (player, unregistered, _) = tickets.remove(five_matches);
+    for (_, ticket, _) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+    assert_eq!(env.register(unregistered), Err(error(ErrorCode::RoundAlreadySettled)));
+
+    let jackpot = pool * 4000 / 10000;
+    let share = pool * 3000 / 10000 / 5;
+    let owed = jackpot + 5 * share;
+    assert_eq!(env.lottery().outstanding_liabilities, owed);
+
+    // The next round can start before anyone claims; what this round owes
+    // stays set aside from its pool
+    env.start_new_round().unwrap();
+    let next_player = env.bank.new_user();
+    env.mint_ticket(next_player, sets()[0]).unwrap();
+    env.reveal_all();
+    let next_pool = env.bank.get::<RoundResult>(&env.round_result(1)).prize_pool;
+    assert_eq!(next_pool, pool + PRICE - OWNER_FEE - owed);
+
+    for (player, ticket, numbers) in tickets {
+        let before = env.bank.lamports(&player);
+        env.claim(player, ticket).unwrap();
+        let expected = if matches(&numbers, &drawn) == 6 { jackpot } else { share };
+        assert_eq!(env.bank.lamports(&player) - before, expected);
+        assert_eq!(env.claim(player, ticket), Err(error(ErrorCode::PrizeAlreadyClaimed)));
+    }
+    assert_eq!(env.claim(player, unregistered), Err(error(ErrorCode::TicketNotRegistered)));
+
+    let lottery = env.lottery();
+    assert_eq!(lottery.outstanding_liabilities, 0);
+    assert_eq!(lottery.accumulated_prize, pool + PRICE - OWNER_FEE - owed);
+}
+
+#[test]
+fn withdrawing_fees_leaves_the_prize_pool_and_liabilities_alone() {
+    let mut env = six_of_seven();
+    let mut tickets = vec![];
+    for numbers in sets() {
+        let player = env.bank.new_user();
+        tickets.push((player, env.mint_ticket(player, numbers).unwrap()));
+    }
+
+    // A resale at the mint price pays 1% owner and 2% marketplace fees
+    let (seller, ticket) = tickets[0];
+    let buyer = env.bank.new_user();
+    env.list(seller, ticket, PRICE).unwrap();
+    env.buy(buyer, ticket).unwrap();
+    tickets[0].0 = buyer;
+
+    // While the round could still be cancelled only the sale fees come out
+    let collector = env.admin;
+    let before = env.bank.lamports(&collector);
+    env.withdraw_fees(collector).unwrap();
+    assert_eq!(env.bank.lamports(&collector) - before, 3 * PRICE / 100);
+    assert_eq!(env.lottery().protocol_fees_accrued, 7 * OWNER_FEE);
+    assert_eq!(env.lottery().marketplace_fees_accrued, 0);
+
+    env.reveal_all();
+    for (_, ticket) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+    let settled = env.lottery();
+    assert!(settled.outstanding_liabilities > 0);
+
+    // Once it's complete the mint fees are earned too
+    let before = env.bank.lamports(&collector);
+    let held = env.bank.lamports(&env.lottery);
+    env.withdraw_fees(collector).unwrap();
+    assert_eq!(env.bank.lamports(&collector) - before, 7 * OWNER_FEE);
+    assert_eq!(held - env.bank.lamports(&env.lottery), 7 * OWNER_FEE);
+    let lottery = env.lottery();
+    assert_eq!(lottery.accumulated_prize, settled.accumulated_prize);
+    assert_eq!(lottery.outstanding_liabilities, settled.outstanding_liabilities);
+    assert_eq!(lottery.protocol_fees_accrued, 0);
+    assert_eq!(env.withdraw_fees(collector), Err(error(ErrorCode::InsufficientFees)));
+
+    // Every winner can still be paid in full
+    for (player, ticket) in tickets {
+        env.claim(player, ticket).unwrap();
+    }
+    assert_eq!(env.lottery().outstanding_liabilities, 0);
+}
+
+#[test]
+fn listings_lapse_when_the_round_completes() {
+    let mut env = six_of_seven();
+    let mut tickets = vec![];
+    for numbers in sets() {
+        let player = env.bank.new_user();
+        let ticket = env.mint_ticket(player, numbers).unwrap();
+        env.list(player, ticket, PRICE).unwrap();
+        tickets.push((player, ticket));
+    }
+
+    // Still listed when the last number comes out, but nobody can buy them now
+    env.reveal_all();
+    let buyer = env.bank.new_user();
+    assert_eq!(env.buy(buyer, tickets[0].1), Err(error(ErrorCode::ListingExpired)));
+    for (_, ticket) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+
+    // So the listings don't hold up claims, and sellers can still take back
+    // their rent
+    for (player, ticket) in tickets {
+        let before = env.bank.lamports(&player);
+        env.claim(player, ticket).unwrap();
+        assert!(env.bank.lamports(&player) > before);
+        env.delist(player, ticket).unwrap();
+        assert!(env.bank.account(&env.listing(ticket)).is_none());
+    }
+}
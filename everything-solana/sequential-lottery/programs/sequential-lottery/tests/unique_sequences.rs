@@ -0,0 +1,46 @@
+//! Each sequence sells once per round, keyed by how tickets are scored.
+
+mod common;
+
+use common::{already_in_use, params, Env};
+use sequential_lottery::MatchMode;
+
+fn playing(match_mode: MatchMode) -> Env {
+    let mut params = params();
+    params.match_mode = match_mode;
+    Env::new(params)
+}
+
+#[test]
+fn a_permuted_set_is_a_duplicate() {
+    let mut env = playing(MatchMode::UnorderedSet);
+    let player = env.bank.new_user();
+    env.mint_ticket(player, [6, 5, 4, 3, 2, 1]).unwrap();
+
+    // Same numbers in another order score the same, so the claim exists
+    assert_eq!(env.mint_ticket(player, [1, 2, 3, 4, 5, 6]), Err(already_in_use()));
+    assert_eq!(env.lottery().next_ticket_id, 1);
+    env.mint_ticket(player, [1, 2, 3, 4, 5, 7]).unwrap();
+}
+
+#[test]
+fn ordered_modes_only_reject_the_same_order() {
+    for match_mode in [MatchMode::OrderedExact, MatchMode::OrderedPrefix] {
+        let mut env = playing(match_mode);
+        let player = env.bank.new_user();
+        env.mint_ticket(player, [6, 5, 4, 3, 2, 1]).unwrap();
+        env.mint_ticket(player, [1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(env.mint_ticket(player, [6, 5, 4, 3, 2, 1]), Err(already_in_use()));
+    }
+}
+
+#[test]
+fn a_sequence_sells_again_in_the_next_round() {
+    let mut env = playing(MatchMode::UnorderedSet);
+    let player = env.bank.new_user();
+    env.mint_ticket(player, [6, 5, 4, 3, 2, 1]).unwrap();
+    let admin = env.admin;
+    env.cancel(admin).unwrap();
+    env.start_new_round().unwrap();
+    env.mint_ticket(player, [1, 2, 3, 4, 5, 6]).unwrap();
+}
@@ -1,16 +1,204 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
+import {
+  BPF_LOADER_UPGRADEABLE_PROGRAM_ID,
+  PublicKey,
+  SYSVAR_SLOT_HASHES_PUBKEY,
+} from "@solana/web3.js";
+import { createHash } from "crypto";
+import { assert } from "chai";
 import { SequentialLottery } from "../target/types/sequential_lottery";
 
 describe("sequential-lottery", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
 
   const program = anchor.workspace.sequentialLottery as Program<SequentialLottery>;
+  const user = provider.wallet.publicKey;
+
+  const [lottery] = PublicKey.findProgramAddressSync(
+    [Buffer.from("lottery")],
+    program.programId
+  );
+  const [config] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  const [programData] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+  );
+  const [roundResult] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("round"),
+      lottery.toBuffer(),
+      new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
 
   it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+    await program.methods
+      .initialize({
+        prizeTiers: [0, 0, 0, 500, 1500, 3000, 5000],
+        salesCloseIndex: 3,
+        priceMultipliers: [10000, 12000, 15000, 15000, 15000, 15000],
+        minNumber: 1,
+        maxNumber: 49,
+        allowRepeats: false,
+        matchMode: { orderedExact: {} },
+      })
+      .accountsPartial({
+        lottery,
+        roundResult,
+        config,
+        user,
+        program: program.programId,
+        programData,
+      })
+      .rpc();
+
+    const state = await program.account.lottery.fetch(lottery);
+    assert.equal(state.currentRound.toNumber(), 0);
+    assert.equal(state.currentRevealIndex, 0);
+    assert.deepEqual(state.prizeTiers, [0, 0, 0, 500, 1500, 3000, 5000]);
+
+    // The deployer holds every role until it hands them out
+    const roles = await program.account.config.fetch(config);
+    assert.isTrue(roles.admin.equals(user));
+    assert.isTrue(roles.revealer.equals(user));
+    assert.isTrue(roles.feeCollector.equals(user));
+    assert.equal(roles.threshold, 0);
+  });
+
+  it("mints a ticket into the current round", async () => {
+    const numbers = [3, 14, 15, 9, 26, 5];
+    const [ticket] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ticket"),
+        lottery.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [sequenceClaim] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("sequence"),
+        lottery.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+        createHash("sha256").update(Buffer.from(numbers)).digest(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .mintTicket(numbers)
+      .accountsPartial({
+        lottery,
+        config,
+        ticket,
+        sequenceClaim,
+        user,
+        slotHashes: SYSVAR_SLOT_HASHES_PUBKEY,
+      })
+      .rpc();
+
+    const minted = await program.account.ticket.fetch(ticket);
+    assert.deepEqual(minted.numbers, numbers);
+    assert.equal(minted.round.toNumber(), 0);
+    assert.isTrue(minted.owner.equals(user));
+
+    // Minting before any reveal pays the base price, less the owner fee
+    const state = await program.account.lottery.fetch(lottery);
+    assert.equal(
+      state.accumulatedPrize.toNumber(),
+      minted.prizeContribution.toNumber()
+    );
+  });
+
+  describe("multisig", () => {
+    const approver = anchor.web3.Keypair.generate();
+    const newRevealer = anchor.web3.Keypair.generate().publicKey;
+    const grantRevealer = {
+      grantRole: { role: { revealer: {} }, account: newRevealer },
+    };
+    const [proposal] = PublicKey.findProgramAddressSync(
+      [Buffer.from("proposal"), new anchor.BN(0).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    const expectError = async (
+      attempt: Promise<unknown>,
+      errorName: string
+    ) => {
+      try {
+        await attempt;
+      } catch (err) {
+        assert.include(err.toString(), errorName);
+        return;
+      }
+      assert.fail(`expected ${errorName}`);
+    };
+
+    const grant = (withProposal: PublicKey | null) =>
+      program.methods
+        .grantRole({ revealer: {} }, newRevealer)
+        .accountsPartial({ config, admin: user, proposal: withProposal })
+        .rpc();
+
+    before(async () => {
+      await program.methods
+        .setApprovers([user, approver.publicKey], 2)
+        .accountsPartial({ config, admin: user, proposal: null })
+        .rpc();
+    });
+
+    it("needs a proposal even from the admin", async () => {
+      await expectError(grant(null), "ProposalRequired");
+      await expectError(
+        program.methods
+          .cancelRound()
+          .accountsPartial({
+            lottery,
+            roundResult,
+            config,
+            caller: user,
+            proposal: null,
+          })
+          .rpc(),
+        "ProposalRequired"
+      );
+    });
+
+    it("executes a proposal once it has enough approvals", async () => {
+      await program.methods
+        .createProposal(grantRevealer)
+        .accountsPartial({ config, proposal, approver: user })
+        .rpc();
+      await expectError(grant(proposal), "ThresholdNotMet");
+
+      await program.methods
+        .approveProposal()
+        .accountsPartial({ config, proposal, approver: approver.publicKey })
+        .signers([approver])
+        .rpc();
+      await grant(proposal);
+
+      const roles = await program.account.config.fetch(config);
+      assert.isTrue(roles.revealer.equals(newRevealer));
+      await expectError(grant(proposal), "ProposalAlreadyExecuted");
+    });
+
+    it("rejects a proposal for a different action", async () => {
+      await expectError(
+        program.methods
+          .revokeRole({ feeCollector: {} })
+          .accountsPartial({ config, admin: user, proposal })
+          .rpc(),
+        "ProposalMismatch"
+      );
+    });
   });
 });
@@ -0,0 +1,58 @@
+use anchor_lang::prelude::*;
+use sol::cpi::accounts::FulfillReveal;
+use sol::program::SequenceLottery;
+use sol::ORACLE_AUTHORITY_SEED;
+
+declare_id!("4NiSAbSoSpuA2w6g1wfcJMoSwjnd1CGpKPcGW5PehYpv");
+
+// Stand-in for a VRF oracle when testing locally. The off-chain side signs the
+// reveal request's seed with the oracle key and puts the ed25519 verify
+// instruction right before `fulfill`; this program forwards the proof to the
+// lottery signed by its authority PDA.
+#[program]
+pub mod mock_oracle {
+    use super::*;
+
+    pub fn fulfill(ctx: Context<Fulfill>, proof: [u8; 64]) -> Result<()> {
+        let seeds = &[ORACLE_AUTHORITY_SEED, &[ctx.bumps.oracle_authority]];
+        let signer = &[&seeds[..]];
+
+        let fulfill_accounts = FulfillReveal {
+            lottery: ctx.accounts.lottery.to_account_info(),
+            reveal_request: ctx.accounts.reveal_request.to_account_info(),
+            oracle_authority: ctx.accounts.oracle_authority.to_account_info(),
+            instructions: ctx.accounts.instructions.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.lottery_program.to_account_info(),
+            fulfill_accounts,
+            signer,
+        );
+
+        sol::cpi::fulfill_reveal(cpi_ctx, proof)
+    }
+}
+
+#[derive(Accounts)]
+pub struct Fulfill<'info> {
+    #[account(
+        seeds = [ORACLE_AUTHORITY_SEED],
+        bump,
+    )]
+    /// CHECK: This is a PDA that signs fulfillments on behalf of the oracle
+    pub oracle_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the lottery program
+    #[account(mut)]
+    pub lottery: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the lottery program
+    #[account(mut)]
+    pub reveal_request: UncheckedAccount<'info>,
+
+    /// CHECK: This is the instructions sysvar, validated by the lottery program
+    pub instructions: UncheckedAccount<'info>,
+
+    pub lottery_program: Program<'info, SequenceLottery>,
+}
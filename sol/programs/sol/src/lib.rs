@@ -1,35 +1,151 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, Transfer};
+use anchor_lang::solana_program::hash::{hash, hashv};
+use match_rules::{matches_revealed, sequence_key, Rule};
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anchor_spl::token_2022_extensions::transfer_fee::{
+    harvest_withheld_tokens_to_mint, HarvestWithheldTokensToMint,
+};
+use anchor_spl::token_interface::{
+    self, get_mint_extension_data, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+// Deprecated in favour of solana-instructions-sysvar, but saves a direct dependency
+#[allow(deprecated)]
+use anchor_lang::solana_program::sysvar::instructions as instructions_sysvar;
 use std::mem::size_of;
 
 declare_id!("BzGNGfCEfvbWYTByUicHR3MmcJYz9DwTMHvtrFmaBAgG");
 
+/// Seed of the PDA an oracle program signs `fulfill_reveal` with.
+pub const ORACLE_AUTHORITY_SEED: &[u8] = b"oracle";
+
+/// Reveal intervals without a reveal after which anyone may cancel.
+const REVEAL_TIMEOUT_INTERVALS: i64 = 3;
+
+/// Longest reveal interval `initialize` accepts, 30 days.
+const MAX_REVEAL_INTERVAL: i64 = 30 * 24 * 60 * 60;
+
+/// Bits of `Lottery::paused`, one per group of operations that can be halted.
+pub const PAUSE_BUY: u8 = 1 << 0;
+pub const PAUSE_REVEAL: u8 = 1 << 1;
+pub const PAUSE_CLAIM: u8 = 1 << 2;
+pub const PAUSE_TRANSFER: u8 = 1 << 3;
+pub const PAUSE_MARKETPLACE: u8 = 1 << 4;
+
+/// Highest marketplace fee `configure_marketplace` accepts, 10%.
+const MAX_MARKETPLACE_FEE_BPS: u16 = 1000;
+
+/// Largest approver set a lottery can have, so approvals fit a u8 bitmask.
+pub const MAX_APPROVERS: usize = 8;
+
 #[program]
 pub mod sequence_lottery {
     use super::*;
 
-    pub fn initialize(
-        ctx: Context<Initialize>,
-        sequence_length: u8,
-        reveal_interval: i64,
-        ticket_price: u64,
-        prize_amount: u64,
-    ) -> Result<()> {
+    pub fn initialize_registry(ctx: Context<InitializeRegistry>) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        registry.authority = ctx.accounts.authority.key();
+        registry.lottery_count = 0;
+        registry.bump = ctx.bumps.registry;
+        
+        Ok(())
+    }
+
+    pub fn initialize(ctx: Context<Initialize>, params: InitializeParams) -> Result<()> {
+        let InitializeParams {
+            sequence_length,
+            reveal_interval,
+            ticket_price,
+            prize_tiers,
+            seed_commitments,
+            settlement_period,
+            sales_close_index,
+            price_multipliers,
+            unique_sequences,
+            min_number,
+            max_number,
+            allow_repeats,
+            match_mode,
+        } = params;
+        
         require!(sequence_length > 0 && sequence_length <= 10, LotteryError::InvalidSequenceLength);
-        require!(reveal_interval > 0, LotteryError::InvalidRevealInterval);
+        require!(
+            reveal_interval > 0 && reveal_interval <= MAX_REVEAL_INTERVAL,
+            LotteryError::InvalidRevealInterval
+        );
+        // Without repeats the range has to hold a full sequence of distinct numbers
+        require!(
+            min_number <= max_number
+                && (allow_repeats || (max_number - min_number) as u16 + 1 >= sequence_length as u16),
+            LotteryError::InvalidNumberRange
+        );
+        require!(settlement_period > 0, LotteryError::InvalidSettlementPeriod);
+        require!(
+            sales_close_index > 0 && sales_close_index <= sequence_length,
+            LotteryError::InvalidSalesCloseIndex
+        );
         require!(ticket_price > 0, LotteryError::InvalidTicketPrice);
-        require!(prize_amount > 0, LotteryError::InvalidPrizeAmount);
+        require!(
+            valid_price_multipliers(&price_multipliers, sales_close_index),
+            LotteryError::InvalidPriceMultipliers
+        );
+        require!(
+            valid_prize_tiers(&prize_tiers, sequence_length),
+            LotteryError::InvalidPrizeTiers
+        );
+
+        // Each lottery takes the next id from the authority's registry
+        let registry = &mut ctx.accounts.registry;
+        let lottery_id = registry.lottery_count;
+        registry.lottery_count += 1;
+        
+        let registry_entry = &mut ctx.accounts.registry_entry;
+        registry_entry.lottery = ctx.accounts.lottery.key();
+        registry_entry.authority = ctx.accounts.authority.key();
+        registry_entry.bump = ctx.bumps.registry_entry;
 
         let lottery = &mut ctx.accounts.lottery;
         lottery.authority = ctx.accounts.authority.key();
+        lottery.pending_authority = Pubkey::default();
+        lottery.paused = 0;
+        lottery.treasury = Pubkey::default();
+        lottery.marketplace_fee_bps = 0;
+        lottery.unallocated_swept = false;
+        lottery.approvers = [Pubkey::default(); MAX_APPROVERS];
+        lottery.approver_count = 0;
+        lottery.threshold = 0;
+        lottery.approvers_epoch = 0;
+        lottery.proposal_count = 0;
+        lottery.lottery_id = lottery_id;
+        lottery.registry = registry.key();
+        lottery.mint = ctx.accounts.mint.key();
+        lottery.vault_bump = ctx.bumps.lottery_vault;
         lottery.sequence_length = sequence_length;
         lottery.reveal_interval = reveal_interval;
         lottery.ticket_price = ticket_price;
-        lottery.prize_amount = prize_amount;
+        lottery.price_multipliers = price_multipliers;
+        lottery.unique_sequences = unique_sequences;
+        lottery.min_number = min_number;
+        lottery.max_number = max_number;
+        lottery.allow_repeats = allow_repeats;
+        lottery.match_mode = match_mode;
+        lottery.prize_tiers = prize_tiers;
+        lottery.prize_pool = 0;
+        lottery.total_paid = 0;
+        lottery.winner_counts = [0; 11];
+        lottery.settlement_period = settlement_period;
+        lottery.settlement_deadline = 0;
+        lottery.sales_close_index = sales_close_index;
         lottery.next_ticket_id = 1;
         lottery.winning_sequence = [0; 10]; // Will be set when drawing
+        lottery.seed_commitments = seed_commitments;
+        lottery.revealed_seeds = [[0; 32]; 10];
+        lottery.randomness_source = RandomnessSource::CommitReveal;
+        lottery.oracle_program = Pubkey::default();
+        lottery.oracle_signer = Pubkey::default();
         lottery.current_reveal_index = 0;
         lottery.last_reveal_timestamp = 0;
+        lottery.created_at = Clock::get()?.unix_timestamp;
         lottery.state = LotteryState::Active;
         
         Ok(())
@@ -37,16 +153,40 @@ pub mod sequence_lottery {
 
     pub fn buy_ticket(ctx: Context<BuyTicket>, sequence: [u8; 10]) -> Result<()> {
         let lottery = &ctx.accounts.lottery;
+        require!(lottery.paused & PAUSE_BUY == 0, LotteryError::Paused);
         require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+        require!(
+            lottery.current_reveal_index < lottery.sales_close_index,
+            LotteryError::SalesClosed
+        );
+        
+        let picked = &sequence[..lottery.sequence_length as usize];
+        for value in picked {
+            require!(
+                *value >= lottery.min_number && *value <= lottery.max_number,
+                LotteryError::InvalidSequenceValue
+            );
+        }
+        require!(
+            lottery.allow_repeats || !has_repeats(picked),
+            LotteryError::RepeatedNumber
+        );
         
-        // Validate sequence values (assuming numbers 1-45 for a lottery)
-        for i in 0..lottery.sequence_length as usize {
-            require!(sequence[i] > 0 && sequence[i] <= 45, LotteryError::InvalidSequenceValue);
+        // With unique sequences on, the claim account's creation is what
+        // rejects a sequence someone already holds
+        if lottery.unique_sequences {
+            let sequence_claim = ctx
+                .accounts
+                .sequence_claim
+                .as_mut()
+                .ok_or(LotteryError::SequenceClaimRequired)?;
+            sequence_claim.ticket = ctx.accounts.ticket.key();
         }
         
         // Transfer tokens from buyer to lottery vault
-        let transfer_instruction = Transfer {
+        let transfer_instruction = TransferChecked {
             from: ctx.accounts.buyer_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
             to: ctx.accounts.lottery_vault.to_account_info(),
             authority: ctx.accounts.buyer.to_account_info(),
         };
@@ -56,7 +196,14 @@ pub mod sequence_lottery {
             transfer_instruction,
         );
         
-        token::transfer(cpi_ctx, lottery.ticket_price)?;
+        let price = current_ticket_price(lottery)?;
+        token_interface::transfer_checked(cpi_ctx, price, ctx.accounts.mint.decimals)?;
+        
+        // A transfer-fee mint withholds part of the payment, so only what the
+        // vault actually received goes into the pool
+        let received = price
+            .checked_sub(transfer_fee(&ctx.accounts.mint, price)?)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
         
         // Create ticket
         let ticket = &mut ctx.accounts.ticket;
@@ -65,75 +212,215 @@ pub mod sequence_lottery {
         ticket.ticket_id = lottery.next_ticket_id;
         ticket.sequence = sequence;
         ticket.claimed = false;
+        ticket.registered = false;
+        ticket.matches = 0;
+        ticket.amount_paid = received;
+        ticket.refunded = false;
+        ticket.listed = false;
+        
+        emit!(TicketPurchased {
+            lottery: lottery.key(),
+            ticket_id: ticket.ticket_id,
+            buyer: ticket.owner,
+            reveal_index: lottery.current_reveal_index,
+            price,
+        });
         
         // Update lottery
         let lottery = &mut ctx.accounts.lottery;
         lottery.next_ticket_id += 1;
+        lottery.prize_pool = lottery
+            .prize_pool
+            .checked_add(received)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
         
         Ok(())
     }
     
-    pub fn reveal_next_number(ctx: Context<RevealNumber>, random_seed: u64) -> Result<()> {
+    pub fn reveal_next_number(ctx: Context<RevealNumber>, seed: [u8; 32]) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        authorize(
+            lottery,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::RevealNextNumber {
+                reveal_index: lottery.current_reveal_index,
+            },
+        )?;
+        
         let lottery = &mut ctx.accounts.lottery;
-        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
         require!(
-            lottery.current_reveal_index < lottery.sequence_length,
-            LotteryError::AllNumbersRevealed
+            lottery.randomness_source == RandomnessSource::CommitReveal,
+            LotteryError::WrongRandomnessSource
         );
         
         let current_time = Clock::get()?.unix_timestamp;
+        check_reveal_ready(lottery, current_time)?;
         
-        // Check if enough time has passed since last reveal
-        if lottery.current_reveal_index > 0 {
-            require!(
-                current_time >= lottery.last_reveal_timestamp + lottery.reveal_interval,
-                LotteryError::TooEarlyForReveal
-            );
-        }
+        // Store the index in a local variable to avoid borrowing issues
+        let current_index = lottery.current_reveal_index as usize;
         
-        // Generate pseudo-random number based on various inputs
-        let random_value = generate_random_number(
-            random_seed,
-            lottery.current_reveal_index,
-            current_time,
-            ctx.accounts.authority.key().to_bytes(),
+        // The seed must be the preimage committed to at initialization
+        require!(
+            hash(&seed).to_bytes() == lottery.seed_commitments[current_index],
+            LotteryError::InvalidSeedReveal
         );
         
-        // Store the index in a local variable to avoid borrowing issues
-        let current_index = lottery.current_reveal_index as usize;
+        lottery.revealed_seeds[current_index] = seed;
+        record_reveal(lottery, &seed, current_time);
+        
+        Ok(())
+    }
+    
+    pub fn configure_oracle(
+        ctx: Context<ConfigureOracle>,
+        oracle_program: Pubkey,
+        oracle_signer: Pubkey,
+    ) -> Result<()> {
+        authorize(
+            &ctx.accounts.lottery,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::ConfigureOracle {
+                oracle_program,
+                oracle_signer,
+            },
+        )?;
         
-        // Set the winning number (1-45)
-        lottery.winning_sequence[current_index] = (random_value % 45 + 1) as u8;
+        let lottery = &mut ctx.accounts.lottery;
+        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
         
-        lottery.current_reveal_index += 1;
-        lottery.last_reveal_timestamp = current_time;
+        // The randomness source can't change once players have bought in
+        require!(
+            lottery.next_ticket_id == 1 && lottery.current_reveal_index == 0,
+            LotteryError::LotteryAlreadyStarted
+        );
         
-        // Check if we've revealed all numbers
-        if lottery.current_reveal_index == lottery.sequence_length {
-            lottery.state = LotteryState::Completed;
-        }
+        lottery.randomness_source = RandomnessSource::Oracle;
+        lottery.oracle_program = oracle_program;
+        lottery.oracle_signer = oracle_signer;
         
         Ok(())
     }
     
-    pub fn claim_prize(ctx: Context<ClaimPrize>) -> Result<()> {
+    pub fn request_reveal(ctx: Context<RequestReveal>) -> Result<()> {
         let lottery = &ctx.accounts.lottery;
+        require!(
+            lottery.randomness_source == RandomnessSource::Oracle,
+            LotteryError::WrongRandomnessSource
+        );
+        
+        let clock = Clock::get()?;
+        check_reveal_ready(lottery, clock.unix_timestamp)?;
+        
+        // The oracle signs this seed; its signature is the proof and its hash
+        // the randomness
+        let request = &mut ctx.accounts.reveal_request;
+        request.lottery = lottery.key();
+        request.reveal_index = lottery.current_reveal_index;
+        request.seed = hashv(&[
+            lottery.key().as_ref(),
+            &[lottery.current_reveal_index],
+            &clock.slot.to_le_bytes(),
+        ])
+        .to_bytes();
+        request.randomness = [0; 32];
+        request.requested_at = clock.unix_timestamp;
+        request.fulfilled = false;
+        request.bump = ctx.bumps.reveal_request;
+        
+        Ok(())
+    }
+    
+    pub fn fulfill_reveal(ctx: Context<FulfillReveal>, proof: [u8; 64]) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        let request = &mut ctx.accounts.reveal_request;
+        
+        require!(lottery.paused & PAUSE_REVEAL == 0, LotteryError::Paused);
+        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+        require!(
+            lottery.randomness_source == RandomnessSource::Oracle,
+            LotteryError::WrongRandomnessSource
+        );
+        require!(!request.fulfilled, LotteryError::RequestAlreadyFulfilled);
+        
+        require!(
+            oracle_proof::proof_verified(
+                &ctx.accounts.instructions,
+                &lottery.oracle_signer,
+                &request.seed,
+                &proof,
+            ),
+            LotteryError::InvalidOracleProof
+        );
+        
+        let randomness = hash(&proof).to_bytes();
+        request.randomness = randomness;
+        request.fulfilled = true;
+        
+        record_reveal(lottery, &randomness, Clock::get()?.unix_timestamp);
+        
+        Ok(())
+    }
+    
+    pub fn register_winner(ctx: Context<RegisterWinner>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        let ticket = &mut ctx.accounts.ticket;
+        
+        require!(lottery.state == LotteryState::Settling, LotteryError::NotSettling);
+        require!(
+            Clock::get()?.unix_timestamp < lottery.settlement_deadline,
+            LotteryError::SettlementClosed
+        );
+        require!(!ticket.registered, LotteryError::AlreadyRegistered);
+        
+        let matches = count_matches(
+            &ticket.sequence,
+            &lottery.winning_sequence,
+            lottery.sequence_length,
+            lottery.match_mode,
+        );
+        require!(lottery.prize_tiers[matches as usize] > 0, LotteryError::NotWinningTicket);
+        
+        lottery.winner_counts[matches as usize] += 1;
+        ticket.registered = true;
+        ticket.matches = matches;
+        
+        Ok(())
+    }
+    
+    pub fn finalize_settlement(ctx: Context<FinalizeSettlement>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        
+        require!(lottery.state == LotteryState::Settling, LotteryError::NotSettling);
+        require!(
+            Clock::get()?.unix_timestamp >= lottery.settlement_deadline,
+            LotteryError::SettlementStillOpen
+        );
+        
+        lottery.state = LotteryState::Completed;
+        
+        Ok(())
+    }
+    
+    pub fn claim_prize(ctx: Context<ClaimPrize>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
         let ticket = &mut ctx.accounts.ticket;
         
+        require!(lottery.paused & PAUSE_CLAIM == 0, LotteryError::Paused);
         require!(lottery.state == LotteryState::Completed, LotteryError::LotteryNotCompleted);
         require!(!ticket.claimed, LotteryError::AlreadyClaimed);
         require!(ticket.lottery == lottery.key(), LotteryError::TicketLotteryMismatch);
+        require!(ticket.registered, LotteryError::TicketNotRegistered);
         
-        // Check if ticket has winning sequence
-        let mut is_winner = true;
-        for i in 0..lottery.sequence_length as usize {
-            if ticket.sequence[i] != lottery.winning_sequence[i] {
-                is_winner = false;
-                break;
-            }
-        }
+        // Registered winners split their tier's share of the pool equally
+        let prize = winner_share(lottery, ticket.matches);
         
-        require!(is_winner, LotteryError::NotWinningTicket);
+        lottery.total_paid = lottery
+            .total_paid
+            .checked_add(prize)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        require!(lottery.total_paid <= lottery.prize_pool, LotteryError::InsufficientPrizePool);
         
         // Transfer prize to winner
         let seeds = &[
@@ -143,8 +430,9 @@ pub mod sequence_lottery {
         ];
         let signer = &[&seeds[..]];
         
-        let transfer_instruction = Transfer {
+        let transfer_instruction = TransferChecked {
             from: ctx.accounts.lottery_vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
             to: ctx.accounts.winner_token_account.to_account_info(),
             authority: ctx.accounts.lottery_authority.to_account_info(),
         };
@@ -155,7 +443,9 @@ pub mod sequence_lottery {
             signer,
         );
         
-        token::transfer(cpi_ctx, lottery.prize_amount)?;
+        // The vault is debited the full prize, keeping it in step with
+        // total_paid; any transfer fee comes out of what the winner receives
+        token_interface::transfer_checked(cpi_ctx, prize, ctx.accounts.mint.decimals)?;
         
         // Mark ticket as claimed
         ticket.claimed = true;
@@ -163,9 +453,308 @@ pub mod sequence_lottery {
         Ok(())
     }
     
+    // Sends the authority whatever settlement didn't allocate to a winner:
+    // tiers nobody registered for, tiers totalling under 100% and rounding
+    // left over from splitting a tier. Registered winners' shares stay put
+    // whether or not they've claimed yet.
+    pub fn sweep_unallocated(ctx: Context<SweepUnallocated>) -> Result<()> {
+        authorize(
+            &ctx.accounts.lottery,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::SweepUnallocated {
+                destination: ctx.accounts.destination.key(),
+            },
+        )?;
+        
+        let lottery = &mut ctx.accounts.lottery;
+        
+        require!(lottery.state == LotteryState::Completed, LotteryError::LotteryNotCompleted);
+        require!(!lottery.unallocated_swept, LotteryError::NothingToSweep);
+        
+        let amount = lottery
+            .prize_pool
+            .checked_sub(allocated_prizes(lottery))
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        require!(amount > 0, LotteryError::NothingToSweep);
+        
+        lottery.unallocated_swept = true;
+        lottery.total_paid = lottery
+            .total_paid
+            .checked_add(amount)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        require!(lottery.total_paid <= lottery.prize_pool, LotteryError::InsufficientPrizePool);
+        
+        let seeds = &[
+            b"lottery".as_ref(),
+            &lottery.key().to_bytes(),
+            &[ctx.bumps.lottery_authority],
+        ];
+        let signer = &[&seeds[..]];
+        
+        let transfer_instruction = TransferChecked {
+            from: ctx.accounts.lottery_vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.lottery_authority.to_account_info(),
+        };
+        
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            signer,
+        );
+        
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+        
+        emit!(UnallocatedSwept {
+            lottery: lottery.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn cancel_lottery(ctx: Context<CancelLottery>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        // Once every number is out the result is known, so nobody can void it
+        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+        
+        // The authority, or an approved proposal while the multisig is on, can
+        // abort any time before that; anyone else only once reveals have stalled
+        let last_activity = if lottery.current_reveal_index > 0 {
+            lottery.last_reveal_timestamp
+        } else {
+            lottery.created_at
+        };
+        let timeout = lottery
+            .reveal_interval
+            .checked_mul(REVEAL_TIMEOUT_INTERVALS)
+            .and_then(|timeout| last_activity.checked_add(timeout))
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        if current_time < timeout {
+            require!(
+                ctx.accounts.caller.key() == lottery.authority || ctx.accounts.proposal.is_some(),
+                LotteryError::RevealsNotStalled
+            );
+            authorize(
+                lottery,
+                ctx.accounts.caller.key(),
+                &mut ctx.accounts.proposal,
+                ProposalAction::CancelLottery,
+            )?;
+        }
+        
+        lottery.state = LotteryState::Cancelled;
+        
+        Ok(())
+    }
+    
+    pub fn refund_ticket(ctx: Context<RefundTicket>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        let ticket = &mut ctx.accounts.ticket;
+        
+        require!(lottery.state == LotteryState::Cancelled, LotteryError::LotteryNotCancelled);
+        require!(!ticket.refunded, LotteryError::AlreadyRefunded);
+        
+        // Refund what the vault actually received for this ticket
+        let refund = ticket.amount_paid;
+        lottery.total_paid = lottery
+            .total_paid
+            .checked_add(refund)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        require!(lottery.total_paid <= lottery.prize_pool, LotteryError::InsufficientPrizePool);
+        
+        let seeds = &[
+            b"lottery".as_ref(),
+            &lottery.key().to_bytes(),
+            &[ctx.bumps.lottery_authority],
+        ];
+        let signer = &[&seeds[..]];
+        
+        let transfer_instruction = TransferChecked {
+            from: ctx.accounts.lottery_vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.owner_token_account.to_account_info(),
+            authority: ctx.accounts.lottery_authority.to_account_info(),
+        };
+        
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+            signer,
+        );
+        
+        token_interface::transfer_checked(cpi_ctx, refund, ctx.accounts.mint.decimals)?;
+        
+        ticket.refunded = true;
+        
+        Ok(())
+    }
+    
+    // Proposing the default pubkey withdraws a pending proposal
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        authorize(
+            &ctx.accounts.lottery,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::ProposeAuthority { new_authority },
+        )?;
+        
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.pending_authority = new_authority;
+        
+        emit!(AuthorityProposed {
+            lottery: lottery.key(),
+            authority: lottery.authority,
+            pending_authority: new_authority,
+        });
+        
+        Ok(())
+    }
+    
+    // The lottery keeps its address, vault and registry id; only who controls it changes
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        let previous_authority = lottery.authority;
+        lottery.authority = lottery.pending_authority;
+        lottery.pending_authority = Pubkey::default();
+        ctx.accounts.registry_entry.authority = lottery.authority;
+        
+        emit!(AuthorityTransferred {
+            lottery: lottery.key(),
+            previous_authority,
+            new_authority: lottery.authority,
+        });
+        
+        Ok(())
+    }
+    
+    // Takes the full set of PAUSE_* bits to halt; 0 resumes everything
+    pub fn set_paused(ctx: Context<SetPaused>, paused: u8) -> Result<()> {
+        authorize(
+            &ctx.accounts.lottery,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::SetPaused { paused },
+        )?;
+        
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.paused = paused;
+        
+        emit!(PauseUpdated {
+            lottery: lottery.key(),
+            paused,
+        });
+        
+        Ok(())
+    }
+    
+    // A threshold of 0 turns the multisig off and hands every privileged
+    // operation back to the authority alone
+    pub fn set_approvers(
+        ctx: Context<SetApprovers>,
+        approvers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        require!(
+            approvers.len() <= MAX_APPROVERS && threshold as usize <= approvers.len(),
+            LotteryError::InvalidApprovers
+        );
+        for (i, approver) in approvers.iter().enumerate() {
+            require!(
+                *approver != Pubkey::default() && !approvers[..i].contains(approver),
+                LotteryError::InvalidApprovers
+            );
+        }
+        
+        let approver_keys: Vec<&[u8]> = approvers.iter().map(|key| key.as_ref()).collect();
+        authorize(
+            &ctx.accounts.lottery,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::SetApprovers {
+                approvers_hash: hashv(&approver_keys).to_bytes(),
+                threshold,
+            },
+        )?;
+        
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.approvers = [Pubkey::default(); MAX_APPROVERS];
+        lottery.approvers[..approvers.len()].copy_from_slice(&approvers);
+        lottery.approver_count = approvers.len() as u8;
+        lottery.threshold = threshold;
+        // Approvals from the old set no longer count towards anything
+        lottery.approvers_epoch += 1;
+        
+        emit!(ApproversUpdated {
+            lottery: lottery.key(),
+            approvers,
+            threshold,
+            approvers_epoch: lottery.approvers_epoch,
+        });
+        
+        Ok(())
+    }
+    
+    // The proposer's own approval comes with the proposal
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: ProposalAction) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        require!(lottery.threshold > 0, LotteryError::MultisigDisabled);
+        let approver_index = approver_index(lottery, &ctx.accounts.approver.key())?;
+        
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.lottery = lottery.key();
+        proposal.id = lottery.proposal_count;
+        proposal.proposer = ctx.accounts.approver.key();
+        proposal.action = action.clone();
+        proposal.approvals = 1 << approver_index;
+        proposal.approvers_epoch = lottery.approvers_epoch;
+        proposal.executed = false;
+        proposal.bump = ctx.bumps.proposal;
+        
+        lottery.proposal_count += 1;
+        
+        emit!(ProposalCreated {
+            lottery: lottery.key(),
+            proposal_id: proposal.id,
+            proposer: proposal.proposer,
+            action,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn approve_proposal(ctx: Context<ApproveProposal>) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, LotteryError::ProposalAlreadyExecuted);
+        require!(
+            proposal.approvers_epoch == lottery.approvers_epoch,
+            LotteryError::StaleProposal
+        );
+        
+        let approver_bit = 1 << approver_index(lottery, &ctx.accounts.approver.key())?;
+        require!(proposal.approvals & approver_bit == 0, LotteryError::AlreadyApproved);
+        proposal.approvals |= approver_bit;
+        
+        emit!(ProposalApproved {
+            lottery: lottery.key(),
+            proposal_id: proposal.id,
+            approver: ctx.accounts.approver.key(),
+            approvals: proposal.approvals.count_ones() as u8,
+        });
+        
+        Ok(())
+    }
+    
     pub fn transfer_ticket(ctx: Context<TransferTicket>) -> Result<()> {
         let ticket = &mut ctx.accounts.ticket;
         let lottery = &ctx.accounts.lottery;
+        require!(lottery.paused & PAUSE_TRANSFER == 0, LotteryError::Paused);
         
         // Ensure the current owner is the signer
         require!(
@@ -178,49 +767,388 @@ pub mod sequence_lottery {
             lottery.state == LotteryState::Active,
             LotteryError::LotteryClosed
         );
+        require!(!ticket.listed, LotteryError::TicketListed);
         
         // Transfer ownership
         ticket.owner = ctx.accounts.new_owner.key();
         
         Ok(())
     }
-}
-
-#[derive(Accounts)]
-#[instruction(sequence_length: u8, reveal_interval: i64, ticket_price: u64, prize_amount: u64)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + size_of::<Lottery>(),
-        seeds = [b"lottery", authority.key().as_ref()],
-        bump
-    )]
-    pub lottery: Account<'info, Lottery>,
-    
-    #[account(
-        seeds = [b"lottery", lottery.key().as_ref()],
-        bump,
-    )]
-    /// CHECK: This is a PDA that acts as the authority for the lottery vault
-    pub lottery_authority: UncheckedAccount<'info>,
-    
-    /// CHECK: This is a token account that will be initialized
-    #[account(mut)]
-    pub lottery_vault: AccountInfo<'info>,
     
-    /// CHECK: This is the token mint
-    pub mint: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    pub fn configure_marketplace(
+        ctx: Context<ConfigureMarketplace>,
+        marketplace_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            marketplace_fee_bps <= MAX_MARKETPLACE_FEE_BPS,
+            LotteryError::InvalidMarketplaceFee
+        );
+        authorize(
+            &ctx.accounts.lottery,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.proposal,
+            ProposalAction::ConfigureMarketplace {
+                treasury: ctx.accounts.treasury.key(),
+                marketplace_fee_bps,
+            },
+        )?;
+        
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.treasury = ctx.accounts.treasury.key();
+        lottery.marketplace_fee_bps = marketplace_fee_bps;
+        
+        Ok(())
+    }
     
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    pub fn list_ticket(ctx: Context<ListTicket>, price: u64) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        require!(lottery.paused & PAUSE_MARKETPLACE == 0, LotteryError::Paused);
+        // Tickets only trade while the lottery is still drawing
+        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+        require!(lottery.treasury != Pubkey::default(), LotteryError::MarketplaceNotConfigured);
+        require!(price > 0, LotteryError::InvalidTicketPrice);
+        
+        // The listed flag locks the ticket against transfers and offers. A listing
+        // can't be bought once the lottery leaves Active, so claims and refunds
+        // ignore it
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.listed = true;
+        
+        let listing = &mut ctx.accounts.listing;
+        listing.lottery = lottery.key();
+        listing.ticket = ticket.key();
+        listing.seller = ticket.owner;
+        listing.price = price;
+        listing.bump = ctx.bumps.listing;
+        
+        emit!(TicketListed {
+            lottery: lottery.key(),
+            ticket_id: ticket.ticket_id,
+            seller: ticket.owner,
+            price,
+        });
+        
+        Ok(())
+    }
+    
+    // Closing the listing returns its rent to the seller
+    pub fn delist_ticket(ctx: Context<DelistTicket>) -> Result<()> {
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.listed = false;
+        
+        emit!(TicketDelisted {
+            lottery: ticket.lottery,
+            ticket_id: ticket.ticket_id,
+            seller: ctx.accounts.seller.key(),
+        });
+        
+        Ok(())
+    }
+    
+    pub fn purchase_listed_ticket(ctx: Context<PurchaseListedTicket>) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        require!(lottery.paused & PAUSE_MARKETPLACE == 0, LotteryError::Paused);
+        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+        
+        let listing = &ctx.accounts.listing;
+        require!(
+            listing.seller != ctx.accounts.buyer.key(),
+            LotteryError::CannotBuyOwnTicket
+        );
+        
+        let price = listing.price;
+        let marketplace_fee = (price as u128 * lottery.marketplace_fee_bps as u128 / 10_000) as u64;
+        let seller_amount = price
+            .checked_sub(marketplace_fee)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        let decimals = ctx.accounts.mint.decimals;
+        
+        if marketplace_fee > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.buyer_token_account.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.treasury.to_account_info(),
+                        authority: ctx.accounts.buyer.to_account_info(),
+                    },
+                ),
+                marketplace_fee,
+                decimals,
+            )?;
+        }
+        
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.buyer_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.seller_token_account.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            ),
+            seller_amount,
+            decimals,
+        )?;
+        
+        // Payment and ownership change in the same instruction, so either both
+        // happen or neither does
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.owner = ctx.accounts.buyer.key();
+        ticket.listed = false;
+        
+        emit!(TicketSold {
+            lottery: lottery.key(),
+            ticket_id: ticket.ticket_id,
+            seller: ctx.accounts.seller.key(),
+            buyer: ticket.owner,
+            price,
+        });
+        
+        Ok(())
+    }
+    
+    // Escrows `amount` in a token account owned by the offer PDA until the
+    // offer is accepted, cancelled or expires
+    pub fn place_offer(
+        ctx: Context<PlaceOffer>,
+        nonce: u64,
+        target: OfferTarget,
+        amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        require!(lottery.paused & PAUSE_MARKETPLACE == 0, LotteryError::Paused);
+        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+        require!(lottery.treasury != Pubkey::default(), LotteryError::MarketplaceNotConfigured);
+        require!(amount > 0, LotteryError::InvalidTicketPrice);
+        require!(expires_at > Clock::get()?.unix_timestamp, LotteryError::InvalidOffer);
+        if let OfferTarget::Prefix { length } = target {
+            require!(
+                length > 0 && length <= lottery.current_reveal_index,
+                LotteryError::InvalidOffer
+            );
+        }
+        
+        let transfer_instruction = TransferChecked {
+            from: ctx.accounts.buyer_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_instruction,
+        );
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+        
+        // A fee-bearing mint withholds part of the deposit in the escrow, where
+        // it can't be paid out, so the offer is for the rest
+        let escrowed = amount
+            .checked_sub(transfer_fee(&ctx.accounts.mint, amount)?)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        
+        let offer = &mut ctx.accounts.offer;
+        offer.lottery = lottery.key();
+        offer.buyer = ctx.accounts.buyer.key();
+        offer.nonce = nonce;
+        offer.target = target.clone();
+        offer.amount = escrowed;
+        offer.expires_at = expires_at;
+        offer.bump = ctx.bumps.offer;
+        
+        emit!(OfferPlaced {
+            lottery: lottery.key(),
+            buyer: offer.buyer,
+            nonce,
+            target,
+            amount: escrowed,
+            expires_at,
+        });
+        
+        Ok(())
+    }
+    
+    // Returns the escrowed tokens to the buyer's token account and closes the
+    // offer and its escrow, rent to the buyer. Until the offer expires or the
+    // lottery stops trading, only the buyer may do this.
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        if ctx.accounts.caller.key() != offer.buyer {
+            require!(
+                ctx.accounts.lottery.state != LotteryState::Active
+                    || Clock::get()?.unix_timestamp >= offer.expires_at,
+                LotteryError::OfferStillActive
+            );
+        }
+        
+        pay_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.mint,
+            offer,
+            ctx.accounts.buyer_token_account.to_account_info(),
+            offer.amount,
+        )?;
+        close_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.mint,
+            offer,
+            ctx.accounts.buyer.to_account_info(),
+        )?;
+        
+        emit!(OfferCancelled {
+            lottery: offer.lottery,
+            buyer: offer.buyer,
+            nonce: offer.nonce,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        let offer = &ctx.accounts.offer;
+        let ticket = &ctx.accounts.ticket;
+        require!(lottery.paused & PAUSE_MARKETPLACE == 0, LotteryError::Paused);
+        require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+        require!(
+            Clock::get()?.unix_timestamp < offer.expires_at,
+            LotteryError::OfferExpired
+        );
+        require!(!ticket.listed, LotteryError::TicketListed);
+        require!(offer.buyer != ticket.owner, LotteryError::CannotBuyOwnTicket);
+        require!(
+            offer_matches(&offer.target, ticket, lottery),
+            LotteryError::OfferMismatch
+        );
+        
+        let price = offer.amount;
+        let marketplace_fee = (price as u128 * lottery.marketplace_fee_bps as u128 / 10_000) as u64;
+        let seller_amount = price
+            .checked_sub(marketplace_fee)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        
+        if marketplace_fee > 0 {
+            pay_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow,
+                &ctx.accounts.mint,
+                offer,
+                ctx.accounts.treasury.to_account_info(),
+                marketplace_fee,
+            )?;
+        }
+        pay_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.mint,
+            offer,
+            ctx.accounts.seller_token_account.to_account_info(),
+            seller_amount,
+        )?;
+        close_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.mint,
+            offer,
+            ctx.accounts.buyer.to_account_info(),
+        )?;
+        
+        let ticket = &mut ctx.accounts.ticket;
+        let seller = ticket.owner;
+        ticket.owner = offer.buyer;
+        
+        emit!(OfferAccepted {
+            lottery: lottery.key(),
+            ticket_id: ticket.ticket_id,
+            seller,
+            buyer: offer.buyer,
+            nonce: offer.nonce,
+            price,
+        });
+        
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeRegistry<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<LotteryRegistry>(),
+        seeds = [b"registry", authority.key().as_ref()],
+        bump
+    )]
+    pub registry: Account<'info, LotteryRegistry>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry", authority.key().as_ref()],
+        bump = registry.bump,
+    )]
+    pub registry: Account<'info, LotteryRegistry>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<Lottery>(),
+        seeds = [b"lottery", authority.key().as_ref(), &registry.lottery_count.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<RegistryEntry>(),
+        seeds = [b"entry", registry.key().as_ref(), &registry.lottery_count.to_le_bytes()],
+        bump
+    )]
+    pub registry_entry: Account<'info, RegistryEntry>,
+    
+    #[account(
+        seeds = [b"lottery", lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is a PDA that acts as the authority for the lottery vault
+    pub lottery_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        init,
+        payer = authority,
+        token::mint = mint,
+        token::authority = lottery_authority,
+        token::token_program = token_program,
+        seeds = [b"vault", lottery.key().as_ref()],
+        bump
+    )]
+    pub lottery_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
+#[instruction(sequence: [u8; 10])]
 pub struct BuyTicket<'info> {
     #[account(mut)]
     pub lottery: Account<'info, Lottery>,
@@ -228,100 +1156,725 @@ pub struct BuyTicket<'info> {
     #[account(
         init,
         payer = buyer,
-        space = 8 + size_of::<Ticket>(),
-        seeds = [b"ticket", lottery.key().as_ref(), &lottery.next_ticket_id.to_le_bytes()],
+        space = 8 + size_of::<Ticket>(),
+        seeds = [b"ticket", lottery.key().as_ref(), &lottery.next_ticket_id.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+    
+    // Only needed when the lottery enforces unique sequences
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + size_of::<SequenceClaim>(),
+        seeds = [
+            b"sequence",
+            lottery.key().as_ref(),
+            sequence_key(&sequence[..lottery.sequence_length as usize], lottery.match_mode.into())
+                .as_ref(),
+        ],
+        bump
+    )]
+    pub sequence_claim: Option<Account<'info, SequenceClaim>>,
+    
+    #[account(
+        mut,
+        seeds = [b"vault", lottery.key().as_ref()],
+        bump = lottery.vault_bump,
+    )]
+    pub lottery_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RevealNumber<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    // Must be the lottery's authority, unless a proposal is executed instead
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureOracle<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    // Must be the lottery's authority, unless a proposal is executed instead
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct RequestReveal<'info> {
+    #[account(
+        constraint = lottery.authority == authority.key() @ LotteryError::Unauthorized
+    )]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<RevealRequest>(),
+        seeds = [b"reveal_request", lottery.key().as_ref(), &[lottery.current_reveal_index]],
+        bump
+    )]
+    pub reveal_request: Account<'info, RevealRequest>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FulfillReveal<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        mut,
+        seeds = [b"reveal_request", lottery.key().as_ref(), &[lottery.current_reveal_index]],
+        bump = reveal_request.bump,
+    )]
+    pub reveal_request: Account<'info, RevealRequest>,
+    
+    #[account(
+        constraint = oracle_authority.key()
+            == Pubkey::find_program_address(&[ORACLE_AUTHORITY_SEED], &lottery.oracle_program).0
+            @ LotteryError::UnauthorizedOracle
+    )]
+    pub oracle_authority: Signer<'info>,
+    
+    /// CHECK: This is the instructions sysvar, used to find the ed25519 proof check
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterWinner<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        mut,
+        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+    )]
+    pub ticket: Account<'info, Ticket>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeSettlement<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPrize<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        seeds = [b"lottery", lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is a PDA that acts as the authority for the lottery vault
+    pub lottery_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        constraint = ticket.owner == winner.key() @ LotteryError::NotTicketOwner,
+        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+    )]
+    pub ticket: Account<'info, Ticket>,
+    
+    #[account(
+        mut,
+        seeds = [b"vault", lottery.key().as_ref()],
+        bump = lottery.vault_bump,
+    )]
+    pub lottery_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = winner_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub winner_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    pub winner: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepUnallocated<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        seeds = [b"lottery", lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is a PDA that acts as the authority for the lottery vault
+    pub lottery_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"vault", lottery.key().as_ref()],
+        bump = lottery.vault_bump,
+    )]
+    pub lottery_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = destination.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    // Must be the lottery's authority, unless a proposal is executed instead
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLottery<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    pub caller: Signer<'info>,
+    
+    // Needed to cancel early while the multisig is on
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct RefundTicket<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        seeds = [b"lottery", lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is a PDA that acts as the authority for the lottery vault
+    pub lottery_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        constraint = ticket.owner == owner.key() @ LotteryError::NotTicketOwner,
+        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+    )]
+    pub ticket: Account<'info, Ticket>,
+    
+    #[account(
+        mut,
+        seeds = [b"vault", lottery.key().as_ref()],
+        bump = lottery.vault_bump,
+    )]
+    pub lottery_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    pub owner: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    // Must be the lottery's authority, unless a proposal is executed instead
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        constraint = lottery.pending_authority != Pubkey::default() @ LotteryError::NoPendingAuthority,
+        constraint = lottery.pending_authority == new_authority.key() @ LotteryError::Unauthorized
+    )]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        mut,
+        seeds = [b"entry", lottery.registry.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump = registry_entry.bump,
+    )]
+    pub registry_entry: Account<'info, RegistryEntry>,
+    
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    // Must be the lottery's authority, unless a proposal is executed instead
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct SetApprovers<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    // Must be the lottery's authority, unless a proposal is executed instead
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        init,
+        payer = approver,
+        space = 8 + size_of::<Proposal>(),
+        seeds = [b"proposal", lottery.key().as_ref(), &lottery.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    #[account(mut)]
+    pub approver: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveProposal<'info> {
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        mut,
+        has_one = lottery,
+        seeds = [b"proposal", lottery.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TransferTicket<'info> {
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        mut,
+        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+    )]
+    pub ticket: Account<'info, Ticket>,
+    
+    pub current_owner: Signer<'info>,
+    
+    /// CHECK: This is just an account we're transferring ownership to
+    pub new_owner: UncheckedAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureMarketplace<'info> {
+    #[account(mut)]
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        constraint = treasury.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    
+    // Must be the lottery's authority, unless a proposal is executed instead
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub proposal: Option<Account<'info, Proposal>>,
+}
+
+#[derive(Accounts)]
+pub struct ListTicket<'info> {
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        mut,
+        constraint = ticket.owner == seller.key() @ LotteryError::NotTicketOwner,
+        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+    )]
+    pub ticket: Account<'info, Ticket>,
+    
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + size_of::<Listing>(),
+        seeds = [b"listing", ticket.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, Listing>,
+    
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DelistTicket<'info> {
+    #[account(mut)]
+    pub ticket: Account<'info, Ticket>,
+    
+    #[account(
+        mut,
+        close = seller,
+        has_one = ticket,
+        has_one = seller,
+        seeds = [b"listing", ticket.key().as_ref()],
+        bump = listing.bump,
+    )]
+    pub listing: Account<'info, Listing>,
+    
+    #[account(mut)]
+    pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PurchaseListedTicket<'info> {
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        mut,
+        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+        constraint = ticket.owner == listing.seller @ LotteryError::NotTicketOwner,
+    )]
+    pub ticket: Account<'info, Ticket>,
+    
+    #[account(
+        mut,
+        close = seller,
+        has_one = ticket,
+        has_one = seller,
+        seeds = [b"listing", ticket.key().as_ref()],
+        bump = listing.bump,
+    )]
+    pub listing: Account<'info, Listing>,
+    
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+        constraint = seller_token_account.owner == listing.seller @ LotteryError::NotTicketOwner,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut, address = lottery.treasury @ LotteryError::MarketplaceNotConfigured)]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    /// CHECK: Receives the listing's rent; has_one ties it to the listing's seller
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+    
+    pub buyer: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(nonce: u64)]
+pub struct PlaceOffer<'info> {
+    pub lottery: Account<'info, Lottery>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + size_of::<Offer>(),
+        seeds = [b"offer", buyer.key().as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = offer,
+        token::token_program = token_program,
+        seeds = [b"offer_escrow", offer.key().as_ref()],
         bump
     )]
-    pub ticket: Account<'info, Ticket>,
+    pub escrow: InterfaceAccount<'info, TokenAccount>,
     
-    /// CHECK: This is the lottery vault token account
-    #[account(mut)]
-    pub lottery_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
     
-    /// CHECK: This is the buyer's token account
-    #[account(mut)]
-    pub buyer_token_account: AccountInfo<'info>,
+    #[account(address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
     
     #[account(mut)]
     pub buyer: Signer<'info>,
     
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct RevealNumber<'info> {
+pub struct CancelOffer<'info> {
+    pub lottery: Account<'info, Lottery>,
+    
     #[account(
         mut,
-        constraint = lottery.authority == authority.key() @ LotteryError::Unauthorized
+        close = buyer,
+        has_one = lottery,
+        has_one = buyer,
+        seeds = [b"offer", buyer.key().as_ref(), &offer.nonce.to_le_bytes()],
+        bump = offer.bump,
     )]
-    pub lottery: Account<'info, Lottery>,
-    
-    pub authority: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct ClaimPrize<'info> {
-    pub lottery: Account<'info, Lottery>,
+    pub offer: Account<'info, Offer>,
     
     #[account(
-        seeds = [b"lottery", lottery.key().as_ref()],
+        mut,
+        seeds = [b"offer_escrow", offer.key().as_ref()],
         bump,
     )]
-    /// CHECK: This is a PDA that acts as the authority for the lottery vault
-    pub lottery_authority: UncheckedAccount<'info>,
+    pub escrow: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
-        constraint = ticket.owner == winner.key() @ LotteryError::NotTicketOwner,
-        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+        constraint = buyer_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+        constraint = buyer_token_account.owner == offer.buyer @ LotteryError::Unauthorized,
     )]
-    pub ticket: Account<'info, Ticket>,
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
     
-    /// CHECK: This is the lottery vault token account
-    #[account(mut)]
-    pub lottery_vault: AccountInfo<'info>,
+    #[account(mut, address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
     
-    /// CHECK: This is the winner's token account
+    /// CHECK: Gets the offer's rent back; has_one ties it to the offer
     #[account(mut)]
-    pub winner_token_account: AccountInfo<'info>,
+    pub buyer: UncheckedAccount<'info>,
     
-    pub winner: Signer<'info>,
+    pub caller: Signer<'info>,
     
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct TransferTicket<'info> {
+pub struct AcceptOffer<'info> {
     pub lottery: Account<'info, Lottery>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = ticket.owner == seller.key() @ LotteryError::NotTicketOwner,
+        constraint = ticket.lottery == lottery.key() @ LotteryError::TicketLotteryMismatch,
+    )]
     pub ticket: Account<'info, Ticket>,
     
-    pub current_owner: Signer<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        has_one = lottery,
+        has_one = buyer,
+        seeds = [b"offer", buyer.key().as_ref(), &offer.nonce.to_le_bytes()],
+        bump = offer.bump,
+    )]
+    pub offer: Account<'info, Offer>,
     
-    /// CHECK: This is just an account we're transferring ownership to
-    pub new_owner: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"offer_escrow", offer.key().as_ref()],
+        bump,
+    )]
+    pub escrow: InterfaceAccount<'info, TokenAccount>,
     
-    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == lottery.mint @ LotteryError::MintMismatch,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut, address = lottery.treasury @ LotteryError::MarketplaceNotConfigured)]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut, address = lottery.mint @ LotteryError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    /// CHECK: Gets the offer's rent back; has_one ties it to the offer
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+    
+    pub seller: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct InitializeParams {
+    pub sequence_length: u8,
+    pub reveal_interval: i64, // Time between reveals in seconds
+    pub ticket_price: u64,
+    pub prize_tiers: [u16; 11], // Basis points of the pool, indexed by match count
+    pub seed_commitments: [[u8; 32]; 10], // sha256 of the seed for each position
+    pub settlement_period: i64, // How long winners have to register after the last reveal
+    pub sales_close_index: u8, // Sales stop once this many numbers are revealed
+    pub price_multipliers: [u32; 10], // Ticket price multiplier in bps per reveal index
+    pub unique_sequences: bool, // Reject a sequence that's already been bought
+    pub min_number: u8, // Smallest number a ticket may pick or the draw may produce
+    pub max_number: u8, // Largest, inclusive
+    pub allow_repeats: bool, // Whether a number may appear twice in one sequence
+    pub match_mode: MatchMode, // How a ticket's matches against the draw are counted
 }
 
 #[account]
-pub struct Lottery {
+pub struct LotteryRegistry {
     pub authority: Pubkey,
+    pub lottery_count: u64, // Lotteries are seeded by ids 0..lottery_count
+    pub bump: u8,
+}
+
+// One per lottery a registry created, seeded by the registry and lottery id so
+// clients can list them all without knowing who runs each one now
+#[account]
+pub struct RegistryEntry {
+    pub lottery: Pubkey,
+    pub authority: Pubkey, // Kept in step with the lottery's by accept_authority
+    pub bump: u8,
+}
+
+#[account]
+pub struct Lottery {
+    pub authority: Pubkey, // Not a seed of anything but the original lottery PDA, so it can change
+    pub pending_authority: Pubkey, // Proposed by the authority, takes over once it accepts
+    pub lottery_id: u64,
+    pub mint: Pubkey, // Every payment in and out uses this mint
+    pub vault_bump: u8,
     pub sequence_length: u8,
     pub reveal_interval: i64, // Time between reveals in seconds
-    pub ticket_price: u64,
-    pub prize_amount: u64,
+    pub ticket_price: u64, // Base price before any numbers are revealed
+    pub price_multipliers: [u32; 10], // Basis points of ticket_price per reveal index
+    pub unique_sequences: bool,
+    pub min_number: u8,
+    pub max_number: u8, // Inclusive
+    pub allow_repeats: bool, // Otherwise draws sample without replacement
+    pub match_mode: MatchMode,
+    pub prize_tiers: [u16; 11], // Basis points of the pool, indexed by match count
+    pub prize_pool: u64, // Total ticket sales
+    pub total_paid: u64, // Prizes and refunds paid out of the vault
+    pub winner_counts: [u32; 11], // Registered winners per match count
+    pub settlement_period: i64, // How long winners have to register after the last reveal
+    pub settlement_deadline: i64,
+    pub sales_close_index: u8, // Sales stop once this many numbers are revealed
     pub next_ticket_id: u64,
     pub winning_sequence: [u8; 10], // Support up to 10 numbers in sequence
+    pub seed_commitments: [[u8; 32]; 10], // sha256 of the seed for each position
+    pub revealed_seeds: [[u8; 32]; 10], // Preimages published by reveal_next_number
+    pub randomness_source: RandomnessSource,
+    pub oracle_program: Pubkey, // Only used with RandomnessSource::Oracle
+    pub oracle_signer: Pubkey, // Key whose ed25519 signature is the oracle proof
     pub current_reveal_index: u8,
     pub last_reveal_timestamp: i64,
+    pub created_at: i64,
     pub state: LotteryState,
+    pub paused: u8, // PAUSE_* bits of the operations currently halted
+    pub treasury: Pubkey, // Token account collecting marketplace fees; unset until configured
+    pub marketplace_fee_bps: u16,
+    pub unallocated_swept: bool, // Set once sweep_unallocated has run
+    pub registry: Pubkey, // Registry that created it, which holds its RegistryEntry
+    pub approvers: [Pubkey; MAX_APPROVERS], // Only the first approver_count are set
+    pub approver_count: u8,
+    pub threshold: u8, // Approvals a proposal needs; 0 leaves the authority acting alone
+    pub approvers_epoch: u64, // Incremented by set_approvers, voiding older proposals
+    pub proposal_count: u64, // Id the next proposal is seeded with
+}
+
+// An operation on one lottery waiting for its approvers. Passing the proposal
+// to the instruction it names runs it once enough of them have signed off.
+#[account]
+pub struct Proposal {
+    pub lottery: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+    pub approvals: u8, // Bit i is set once approvers[i] has approved
+    pub approvers_epoch: u64, // The lottery's approvers_epoch when it was created
+    pub executed: bool,
+    pub bump: u8,
+}
+
+// Each privileged instruction with the arguments and accounts an approver
+// needs to see to know what they're agreeing to
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum ProposalAction {
+    RevealNextNumber { reveal_index: u8 },
+    ConfigureOracle { oracle_program: Pubkey, oracle_signer: Pubkey },
+    SweepUnallocated { destination: Pubkey },
+    CancelLottery,
+    ProposeAuthority { new_authority: Pubkey },
+    SetPaused { paused: u8 },
+    SetApprovers { approvers_hash: [u8; 32], threshold: u8 }, // sha256 of the keys in order
+    ConfigureMarketplace { treasury: Pubkey, marketplace_fee_bps: u16 },
 }
 
 #[account]
@@ -331,12 +1884,207 @@ pub struct Ticket {
     pub ticket_id: u64,
     pub sequence: [u8; 10], // User's chosen sequence
     pub claimed: bool,
+    pub registered: bool, // Counted as a winner during settlement
+    pub matches: u8,
+    pub amount_paid: u64, // What the vault received, refunded on cancellation
+    pub refunded: bool,
+    pub listed: bool, // Locked in a marketplace listing; void once the lottery isn't Active
+}
+
+// A buyer's bid, seeded by buyer and a buyer-chosen nonce. The tokens sit in
+// an escrow token account seeded by the offer and owned by it.
+#[account]
+pub struct Offer {
+    pub lottery: Pubkey,
+    pub buyer: Pubkey,
+    pub nonce: u64,
+    pub target: OfferTarget,
+    pub amount: u64, // What the escrow received
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum OfferTarget {
+    Ticket { ticket_id: u64 },
+    // Any ticket matching the first `length` revealed numbers
+    Prefix { length: u8 },
+}
+
+// One per listed ticket, seeded by the ticket
+#[account]
+pub struct Listing {
+    pub lottery: Pubkey,
+    pub ticket: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64, // In the lottery's mint
+    pub bump: u8,
+}
+
+// Exists once per sequence in lotteries with unique_sequences on, seeded by
+// match_rules::sequence_key so permutations of a set count as one sequence
+#[account]
+pub struct SequenceClaim {
+    pub ticket: Pubkey,
+}
+
+#[account]
+pub struct RevealRequest {
+    pub lottery: Pubkey,
+    pub reveal_index: u8,
+    pub seed: [u8; 32], // Message the oracle must sign
+    pub randomness: [u8; 32], // Hash of the oracle proof, set on fulfillment
+    pub requested_at: i64,
+    pub fulfilled: bool,
+    pub bump: u8,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
 pub enum LotteryState {
     Active,
     Completed,
+    Settling, // All numbers revealed, winners registering
+    Cancelled, // Aborted, tickets can be refunded
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum RandomnessSource {
+    CommitReveal,
+    Oracle,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    OrderedExact, // Same number in the same position
+    UnorderedSet, // Distinct picked numbers that were drawn anywhere, classic lotto
+    OrderedPrefix, // Positions matched from the start until the first miss
+}
+
+impl From<MatchMode> for Rule {
+    fn from(match_mode: MatchMode) -> Self {
+        match match_mode {
+            MatchMode::OrderedExact => Rule::OrderedExact,
+            MatchMode::UnorderedSet => Rule::UnorderedSet,
+            MatchMode::OrderedPrefix => Rule::OrderedPrefix,
+        }
+    }
+}
+
+#[event]
+pub struct TicketPurchased {
+    pub lottery: Pubkey,
+    pub ticket_id: u64,
+    pub buyer: Pubkey,
+    pub reveal_index: u8,
+    pub price: u64,
+}
+
+#[event]
+pub struct TicketListed {
+    pub lottery: Pubkey,
+    pub ticket_id: u64,
+    pub seller: Pubkey,
+    pub price: u64,
+}
+
+#[event]
+pub struct TicketDelisted {
+    pub lottery: Pubkey,
+    pub ticket_id: u64,
+    pub seller: Pubkey,
+}
+
+#[event]
+pub struct TicketSold {
+    pub lottery: Pubkey,
+    pub ticket_id: u64,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+}
+
+#[event]
+pub struct OfferPlaced {
+    pub lottery: Pubkey,
+    pub buyer: Pubkey,
+    pub nonce: u64,
+    pub target: OfferTarget,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct OfferCancelled {
+    pub lottery: Pubkey,
+    pub buyer: Pubkey,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct OfferAccepted {
+    pub lottery: Pubkey,
+    pub ticket_id: u64,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub nonce: u64,
+    pub price: u64,
+}
+
+#[event]
+pub struct UnallocatedSwept {
+    pub lottery: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub lottery: Pubkey,
+    pub paused: u8,
+}
+
+#[event]
+pub struct ApproversUpdated {
+    pub lottery: Pubkey,
+    pub approvers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub approvers_epoch: u64,
+}
+
+#[event]
+pub struct ProposalCreated {
+    pub lottery: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+}
+
+#[event]
+pub struct ProposalApproved {
+    pub lottery: Pubkey,
+    pub proposal_id: u64,
+    pub approver: Pubkey,
+    pub approvals: u8,
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub lottery: Pubkey,
+    pub proposal_id: u64,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub lottery: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub lottery: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
 }
 
 #[error_code]
@@ -347,8 +2095,8 @@ pub enum LotteryError {
     InvalidRevealInterval,
     #[msg("Invalid ticket price")]
     InvalidTicketPrice,
-    #[msg("Invalid prize amount")]
-    InvalidPrizeAmount,
+    #[msg("Invalid prize tiers")]
+    InvalidPrizeTiers,
     #[msg("Invalid sequence value")]
     InvalidSequenceValue,
     #[msg("Lottery is closed")]
@@ -369,17 +2117,493 @@ pub enum LotteryError {
     TicketLotteryMismatch,
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Seed does not match the committed hash")]
+    InvalidSeedReveal,
+    #[msg("Instruction not available for this randomness source")]
+    WrongRandomnessSource,
+    #[msg("Lottery has already started")]
+    LotteryAlreadyStarted,
+    #[msg("Randomness not from the configured oracle")]
+    UnauthorizedOracle,
+    #[msg("Invalid oracle proof")]
+    InvalidOracleProof,
+    #[msg("Reveal request already fulfilled")]
+    RequestAlreadyFulfilled,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Invalid settlement period")]
+    InvalidSettlementPeriod,
+    #[msg("Lottery is not settling")]
+    NotSettling,
+    #[msg("Settlement period has ended")]
+    SettlementClosed,
+    #[msg("Settlement period has not ended yet")]
+    SettlementStillOpen,
+    #[msg("Ticket already registered")]
+    AlreadyRegistered,
+    #[msg("Ticket was not registered as a winner")]
+    TicketNotRegistered,
+    #[msg("Prize pool can't cover this payout")]
+    InsufficientPrizePool,
+    #[msg("Token account mint doesn't match the lottery mint")]
+    MintMismatch,
+    #[msg("Lottery is not cancelled")]
+    LotteryNotCancelled,
+    #[msg("Ticket already refunded")]
+    AlreadyRefunded,
+    #[msg("Reveals haven't stalled long enough to cancel")]
+    RevealsNotStalled,
+    #[msg("Invalid sales close index")]
+    InvalidSalesCloseIndex,
+    #[msg("Ticket sales are closed")]
+    SalesClosed,
+    #[msg("Invalid price multipliers")]
+    InvalidPriceMultipliers,
+    #[msg("This lottery requires a sequence claim account")]
+    SequenceClaimRequired,
+    #[msg("No authority transfer is pending")]
+    NoPendingAuthority,
+    #[msg("This operation is paused")]
+    Paused,
+    #[msg("Invalid number range")]
+    InvalidNumberRange,
+    #[msg("Numbers may not repeat within a sequence")]
+    RepeatedNumber,
+    #[msg("Ticket is listed on the marketplace")]
+    TicketListed,
+    #[msg("Marketplace treasury is not configured")]
+    MarketplaceNotConfigured,
+    #[msg("Invalid marketplace fee")]
+    InvalidMarketplaceFee,
+    #[msg("Cannot buy your own ticket")]
+    CannotBuyOwnTicket,
+    #[msg("Offers need a future expiry and a prefix of numbers already revealed")]
+    InvalidOffer,
+    #[msg("Offer has expired")]
+    OfferExpired,
+    #[msg("Ticket doesn't match the offer")]
+    OfferMismatch,
+    #[msg("Only the buyer can cancel an offer that can still be accepted")]
+    OfferStillActive,
+    #[msg("No unallocated prize money left to sweep")]
+    NothingToSweep,
+    #[msg("Approvers must be distinct, at most 8, with a threshold no larger than the set")]
+    InvalidApprovers,
+    #[msg("This lottery has no multisig")]
+    MultisigDisabled,
+    #[msg("Signer is not one of the lottery's approvers")]
+    NotApprover,
+    #[msg("Approver already approved this proposal")]
+    AlreadyApproved,
+    #[msg("The multisig is on; this operation needs an approved proposal")]
+    ProposalRequired,
+    #[msg("Proposal is for a different lottery or operation")]
+    ProposalMismatch,
+    #[msg("Proposal already executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal predates the current approver set")]
+    StaleProposal,
+    #[msg("Proposal doesn't have enough approvals yet")]
+    ThresholdNotMet,
+}
+
+// Tiers are basis points of the pool per match count. Nothing is paid for zero
+// matches or for counts beyond the sequence, and the tiers can't promise more
+// than the whole pool.
+fn valid_prize_tiers(prize_tiers: &[u16; 11], sequence_length: u8) -> bool {
+    let total: u32 = prize_tiers.iter().map(|&bps| bps as u32).sum();
+    
+    prize_tiers[0] == 0
+        && prize_tiers[sequence_length as usize + 1..].iter().all(|&bps| bps == 0)
+        && total > 0
+        && total <= 10_000
+}
+
+// Buying before any reveal costs the base price, and the price can only go up
+// as more of the sequence becomes public
+fn valid_price_multipliers(price_multipliers: &[u32; 10], sales_close_index: u8) -> bool {
+    let on_sale = &price_multipliers[..sales_close_index as usize];
+    
+    on_sale[0] == 10_000 && on_sale.windows(2).all(|pair| pair[0] <= pair[1])
+}
+
+fn current_ticket_price(lottery: &Lottery) -> Result<u64> {
+    let multiplier = lottery.price_multipliers[lottery.current_reveal_index as usize];
+    let price = lottery.ticket_price as u128 * multiplier as u128 / 10_000;
+    
+    u64::try_from(price).map_err(|_| LotteryError::ArithmeticOverflow.into())
+}
+
+// The one place a ticket is scored against the draw
+fn count_matches(
+    sequence: &[u8; 10],
+    winning_sequence: &[u8; 10],
+    sequence_length: u8,
+    match_mode: MatchMode,
+) -> u8 {
+    let length = sequence_length as usize;
+    match_rules::count_matches(&sequence[..length], &winning_sequence[..length], match_mode.into())
+        as u8
+}
+
+fn tier_payout(prize_pool: u64, tier_bps: u16) -> u64 {
+    // A bps share of a u64 always fits back into a u64
+    (prize_pool as u128 * tier_bps as u128 / 10_000) as u64
+}
+
+// Everything owed to registered winners, claimed or not
+fn allocated_prizes(lottery: &Lottery) -> u64 {
+    (0..=lottery.sequence_length)
+        .map(|matches| {
+            winner_share(lottery, matches) * lottery.winner_counts[matches as usize] as u64
+        })
+        .sum()
+}
+
+// What each registered winner with `matches` matches is owed; zero for tiers
+// without winners
+fn winner_share(lottery: &Lottery, matches: u8) -> u64 {
+    match lottery.winner_counts[matches as usize] {
+        0 => 0,
+        winners => {
+            tier_payout(lottery.prize_pool, lottery.prize_tiers[matches as usize]) / winners as u64
+        }
+    }
+}
+
+// Fee a Token-2022 transfer-fee mint withholds on `amount` this epoch; zero for
+// mints without the extension
+fn transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    match get_mint_extension_data::<TransferFeeConfig>(&mint.to_account_info()) {
+        Ok(fee_config) => fee_config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or_else(|| LotteryError::ArithmeticOverflow.into()),
+        Err(_) => Ok(0),
+    }
+}
+
+fn check_reveal_ready(lottery: &Lottery, current_time: i64) -> Result<()> {
+    require!(lottery.paused & PAUSE_REVEAL == 0, LotteryError::Paused);
+    require!(lottery.state == LotteryState::Active, LotteryError::LotteryClosed);
+    require!(
+        lottery.current_reveal_index < lottery.sequence_length,
+        LotteryError::AllNumbersRevealed
+    );
+    
+    // Check if enough time has passed since last reveal
+    if lottery.current_reveal_index > 0 {
+        require!(
+            current_time >= lottery.last_reveal_timestamp + lottery.reveal_interval,
+            LotteryError::TooEarlyForReveal
+        );
+    }
+    
+    Ok(())
+}
+
+// Sets the number at the current position from `entropy` and advances the reveal
+fn record_reveal(lottery: &mut Lottery, entropy: &[u8; 32], current_time: i64) {
+    let current_index = lottery.current_reveal_index as usize;
+    
+    lottery.winning_sequence[current_index] = draw_number(lottery, entropy);
+    
+    lottery.current_reveal_index += 1;
+    lottery.last_reveal_timestamp = current_time;
+    
+    // Once every number is out, winners get a window to register
+    if lottery.current_reveal_index == lottery.sequence_length {
+        lottery.state = LotteryState::Settling;
+        lottery.settlement_deadline = current_time + lottery.settlement_period;
+    }
+}
+
+// Draws the number at current_reveal_index from min_number..=max_number, using
+// the revealed seed or the oracle randomness as entropy. Without repeats it
+// picks among the numbers not drawn yet, like sampling without replacement.
+// Deterministic, so anyone can recompute it from `revealed_seeds` or the
+// reveal request.
+fn draw_number(lottery: &Lottery, entropy: &[u8; 32]) -> u8 {
+    let index = lottery.current_reveal_index;
+    let digest = hashv(&[entropy, &[index]]).to_bytes();
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&digest[..8]);
+    let value = u64::from_le_bytes(value);
+    
+    let drawn = &lottery.winning_sequence[..index as usize];
+    let range = (lottery.max_number - lottery.min_number) as u64 + 1;
+    let available_count = if lottery.allow_repeats {
+        range
+    } else {
+        range - drawn.len() as u64
+    };
+    
+    // initialize guarantees the range outlasts the sequence, so this always finds one
+    (lottery.min_number..=lottery.max_number)
+        .filter(|number| lottery.allow_repeats || !drawn.contains(number))
+        .nth((value % available_count) as usize)
+        .unwrap_or(lottery.min_number)
+}
+
+fn offer_matches(target: &OfferTarget, ticket: &Ticket, lottery: &Lottery) -> bool {
+    match target {
+        OfferTarget::Ticket { ticket_id } => ticket.ticket_id == *ticket_id,
+        OfferTarget::Prefix { length } => {
+            if *length > lottery.current_reveal_index {
+                return false;
+            }
+            let revealed = &lottery.winning_sequence[..*length as usize];
+            let picked = &ticket.sequence[..lottery.sequence_length as usize];
+            matches_revealed(picked, revealed, lottery.match_mode.into())
+        }
+    }
+}
+
+// Without a multisig only the authority's signature counts. With one, the
+// signer doesn't matter: the proposal has to be for this lottery and exactly
+// this action, approved by enough of the current approvers and not used yet.
+fn authorize(
+    lottery: &Account<Lottery>,
+    signer: Pubkey,
+    proposal: &mut Option<Account<Proposal>>,
+    action: ProposalAction,
+) -> Result<()> {
+    if lottery.threshold == 0 {
+        require!(signer == lottery.authority, LotteryError::Unauthorized);
+        return Ok(());
+    }
+    
+    let proposal = proposal.as_mut().ok_or(LotteryError::ProposalRequired)?;
+    require!(
+        proposal.lottery == lottery.key() && proposal.action == action,
+        LotteryError::ProposalMismatch
+    );
+    require!(!proposal.executed, LotteryError::ProposalAlreadyExecuted);
+    require!(
+        proposal.approvers_epoch == lottery.approvers_epoch,
+        LotteryError::StaleProposal
+    );
+    require!(
+        proposal.approvals.count_ones() >= lottery.threshold as u32,
+        LotteryError::ThresholdNotMet
+    );
+    proposal.executed = true;
+    
+    emit!(ProposalExecuted {
+        lottery: lottery.key(),
+        proposal_id: proposal.id,
+    });
+    
+    Ok(())
+}
+
+fn approver_index(lottery: &Lottery, approver: &Pubkey) -> Result<usize> {
+    lottery.approvers[..lottery.approver_count as usize]
+        .iter()
+        .position(|key| key == approver)
+        .ok_or_else(|| error!(LotteryError::NotApprover))
+}
+
+// Moves tokens out of an offer's escrow, signed by the offer PDA
+fn pay_from_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    offer: &Account<'info, Offer>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let nonce = offer.nonce.to_le_bytes();
+    let seeds = &[b"offer".as_ref(), offer.buyer.as_ref(), &nonce, &[offer.bump]];
+    let signer = &[&seeds[..]];
+    
+    let transfer_instruction = TransferChecked {
+        from: escrow.to_account_info(),
+        mint: mint.to_account_info(),
+        to,
+        authority: offer.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        transfer_instruction,
+        signer,
+    );
+    token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)
+}
+
+// Closes an emptied escrow, returning its rent to `destination`
+fn close_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    offer: &Account<'info, Offer>,
+    destination: AccountInfo<'info>,
+) -> Result<()> {
+    let nonce = offer.nonce.to_le_bytes();
+    let seeds = &[b"offer".as_ref(), offer.buyer.as_ref(), &nonce, &[offer.bump]];
+    let signer = &[&seeds[..]];
+    
+    // Token-2022 won't close an account still holding withheld transfer fees
+    if get_mint_extension_data::<TransferFeeConfig>(&mint.to_account_info()).is_ok() {
+        harvest_withheld_tokens_to_mint(
+            CpiContext::new(
+                token_program.to_account_info(),
+                HarvestWithheldTokensToMint {
+                    token_program_id: token_program.to_account_info(),
+                    mint: mint.to_account_info(),
+                },
+            ),
+            vec![escrow.to_account_info()],
+        )?;
+    }
+    
+    let close_instruction = CloseAccount {
+        account: escrow.to_account_info(),
+        destination,
+        authority: offer.to_account_info(),
+    };
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        close_instruction,
+        signer,
+    ))
+}
+
+fn has_repeats(numbers: &[u8]) -> bool {
+    numbers
+        .iter()
+        .enumerate()
+        .any(|(i, number)| numbers[..i].contains(number))
 }
 
-// Simple pseudo-random number generator function
-fn generate_random_number(seed: u64, index: u8, timestamp: i64, authority: [u8; 32]) -> u64 {
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    use std::hash::{Hash, Hasher};
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // An all-zero account for a test to fill in; borsh reads zeroes as the
+    // first variant of every enum
+    fn blank<T: AnchorDeserialize>() -> T {
+        T::deserialize(&mut &[0u8; 4096][..]).unwrap()
+    }
+    
+    fn sequence(numbers: &[u8]) -> [u8; 10] {
+        let mut sequence = [0; 10];
+        sequence[..numbers.len()].copy_from_slice(numbers);
+        sequence
+    }
+    
+    #[test]
+    fn matches_ignore_positions_past_sequence_length() {
+        let drawn = sequence(&[1, 2, 3, 4, 5, 6]);
+        let picked = sequence(&[1, 2, 3, 9, 9, 9]);
+        for match_mode in [
+            MatchMode::OrderedExact,
+            MatchMode::UnorderedSet,
+            MatchMode::OrderedPrefix,
+        ] {
+            assert_eq!(count_matches(&picked, &drawn, 3, match_mode), 3);
+        }
+    }
+    
+    // Reveals a whole sequence the way record_reveal does
+    fn draw_all(lottery: &mut Lottery, entropy_byte: u8) {
+        for index in 0..lottery.sequence_length {
+            let number = draw_number(lottery, &[entropy_byte.wrapping_add(index); 32]);
+            lottery.winning_sequence[index as usize] = number;
+            lottery.current_reveal_index += 1;
+        }
+    }
+    
+    #[test]
+    fn draw_without_repeats_uses_each_number_once() {
+        // A range exactly as wide as the sequence has to come out as a permutation
+        for entropy_byte in 0..50 {
+            let mut lottery: Lottery = blank();
+            lottery.sequence_length = 6;
+            lottery.min_number = 10;
+            lottery.max_number = 15;
+            draw_all(&mut lottery, entropy_byte);
+            
+            let mut drawn = lottery.winning_sequence[..6].to_vec();
+            drawn.sort();
+            assert_eq!(drawn, vec![10, 11, 12, 13, 14, 15]);
+        }
+    }
+    
+    #[test]
+    fn draw_stays_in_range() {
+        for allow_repeats in [false, true] {
+            let mut lottery: Lottery = blank();
+            lottery.sequence_length = 10;
+            lottery.min_number = 1;
+            lottery.max_number = 49;
+            lottery.allow_repeats = allow_repeats;
+            draw_all(&mut lottery, 3);
+            
+            assert!(lottery.winning_sequence.iter().all(|n| (1..=49).contains(n)));
+            if !allow_repeats {
+                assert!(!has_repeats(&lottery.winning_sequence));
+            }
+        }
+    }
+    
+    #[test]
+    fn prize_tiers_validation() {
+        let mut prize_tiers = [0; 11];
+        prize_tiers[4] = 2_000;
+        prize_tiers[6] = 8_000;
+        assert!(valid_prize_tiers(&prize_tiers, 6));
+        
+        // Paying for zero matches
+        let mut zero_matches = prize_tiers;
+        zero_matches[0] = 1;
+        assert!(!valid_prize_tiers(&zero_matches, 6));
+        
+        // A tier no ticket can reach
+        assert!(!valid_prize_tiers(&prize_tiers, 5));
+        
+        // Over 100%
+        let mut over = prize_tiers;
+        over[5] = 1;
+        assert!(!valid_prize_tiers(&over, 6));
+        
+        assert!(!valid_prize_tiers(&[0; 11], 6));
+    }
     
-    seed.hash(&mut hasher);
-    index.hash(&mut hasher);
-    timestamp.hash(&mut hasher);
-    authority.hash(&mut hasher);
+    #[test]
+    fn price_multipliers_validation() {
+        let mut price_multipliers = [10_000; 10];
+        price_multipliers[1] = 12_000;
+        price_multipliers[2] = 15_000;
+        assert!(valid_price_multipliers(&price_multipliers, 3));
+        
+        // Entries past the close index are never charged, so they aren't checked
+        price_multipliers[3] = 1;
+        assert!(valid_price_multipliers(&price_multipliers, 3));
+        assert!(!valid_price_multipliers(&price_multipliers, 4));
+        
+        let mut discounted = price_multipliers;
+        discounted[0] = 9_000;
+        assert!(!valid_price_multipliers(&discounted, 3));
+    }
     
-    hasher.finish()
-}
\ No newline at end of file
+    #[test]
+    fn unallocated_prize_money_is_everything_winners_are_not_owed() {
+        let mut lottery: Lottery = blank();
+        lottery.sequence_length = 6;
+        lottery.prize_pool = 1_000;
+        lottery.prize_tiers[3] = 3_000;
+        lottery.prize_tiers[6] = 5_000;
+        
+        // Nobody won, so nothing is owed
+        assert_eq!(allocated_prizes(&lottery), 0);
+        
+        // 300 split three ways is 100 each; the jackpot tier has no winners
+        lottery.winner_counts[3] = 3;
+        assert_eq!(winner_share(&lottery, 3), 100);
+        assert_eq!(allocated_prizes(&lottery), 300);
+        
+        // 300 split seven ways leaves 6 of rounding unallocated
+        lottery.winner_counts[3] = 7;
+        assert_eq!(allocated_prizes(&lottery), 294);
+    }
+}
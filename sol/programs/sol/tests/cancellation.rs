@@ -0,0 +1,85 @@
+//! Cancelling a lottery and refunding its tickets out of the vault.
+
+mod common;
+
+use common::{error, params, Env, TICKET_PRICE};
+use sol::{LotteryError, LotteryState, Ticket};
+
+#[test]
+fn authority_cancels_and_tickets_are_refunded_once() {
+    let mut env = Env::new(params(3));
+    let mut tickets = vec![];
+    for picked in [[1, 2, 3], [4, 5, 6]] {
+        let (player, token_account) = env.player(TICKET_PRICE);
+        tickets.push((player, token_account, env.buy(player, token_account, &picked).unwrap()));
+    }
+    let (player, token_account, ticket) = tickets[0];
+
+    assert_eq!(
+        env.refund(player, token_account, ticket),
+        Err(error(LotteryError::LotteryNotCancelled))
+    );
+    let authority = env.authority;
+    env.cancel(authority).unwrap();
+    assert!(env.lottery().state == LotteryState::Cancelled);
+    assert_eq!(env.buy(player, token_account, &[1, 2, 3]), Err(error(LotteryError::LotteryClosed)));
+
+    // Only the ticket's owner can take the refund
+    let (other, other_token_account) = env.player(0);
+    assert_eq!(
+        env.refund(other, other_token_account, ticket),
+        Err(error(LotteryError::NotTicketOwner))
+    );
+
+    for (player, token_account, ticket) in tickets {
+        env.refund(player, token_account, ticket).unwrap();
+        assert_eq!(env.bank.token_balance(&token_account), TICKET_PRICE);
+        assert!(env.bank.get::<Ticket>(&ticket).refunded);
+        assert_eq!(
+            env.refund(player, token_account, ticket),
+            Err(error(LotteryError::AlreadyRefunded))
+        );
+    }
+    assert_eq!(env.lottery().total_paid, 2 * TICKET_PRICE);
+    assert_eq!(env.bank.token_balance(&env.vault()), 0);
+}
+
+#[test]
+fn anyone_cancels_once_reveals_stall() {
+    let mut env = Env::new(params(3));
+    let (player, token_account) = env.player(TICKET_PRICE);
+    env.buy(player, token_account, &[1, 2, 3]).unwrap();
+    env.reveal().unwrap();
+
+    // Three reveal intervals after the last reveal
+    let stranger = env.bank.new_user();
+    let lottery = env.lottery();
+    let stalled_at = lottery.last_reveal_timestamp + 3 * lottery.reveal_interval;
+    env.bank.warp_to(stalled_at - 1);
+    assert_eq!(env.cancel(stranger), Err(error(LotteryError::RevealsNotStalled)));
+    env.bank.warp_to(stalled_at);
+    env.cancel(stranger).unwrap();
+    assert!(env.lottery().state == LotteryState::Cancelled);
+}
+
+#[test]
+fn a_complete_draw_cannot_be_cancelled() {
+    let mut env = Env::new(params(3));
+    env.reveal_all();
+    let authority = env.authority;
+    assert_eq!(env.cancel(authority), Err(error(LotteryError::LotteryClosed)));
+}
+
+#[test]
+fn listed_tickets_are_refunded() {
+    let mut env = Env::new(params(3));
+    env.configure_marketplace(0).unwrap();
+    let (player, token_account) = env.player(TICKET_PRICE);
+    let ticket = env.buy(player, token_account, &[1, 2, 3]).unwrap();
+    env.list(player, ticket, 2 * TICKET_PRICE).unwrap();
+
+    let authority = env.authority;
+    env.cancel(authority).unwrap();
+    env.refund(player, token_account, ticket).unwrap();
+    assert_eq!(env.bank.token_balance(&token_account), TICKET_PRICE);
+}
@@ -0,0 +1,562 @@
+//! Shared setup for the instruction tests: a bank with a lottery initialized
+//! against a fresh mint, and the accounts and instructions the tests keep
+//! needing.
+
+#![allow(dead_code)] // Not every test file uses every helper
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
+use program_harness::{Bank, TransferFee};
+use sol::{accounts, instruction, InitializeParams, MatchMode, OfferTarget, ProposalAction};
+
+pub const TICKET_PRICE: u64 = 1_000_000;
+
+pub struct Env {
+    pub bank: Bank,
+    pub authority: Pubkey,
+    pub lottery: Pubkey,
+    pub mint: Pubkey,
+    pub token_program: Pubkey,
+}
+
+/// The seed revealed for each position; initialize commits to their hashes.
+pub fn seed(index: usize) -> [u8; 32] {
+    [index as u8 + 1; 32]
+}
+
+pub fn params(sequence_length: u8) -> InitializeParams {
+    let mut seed_commitments = [[0; 32]; 10];
+    for (index, commitment) in seed_commitments.iter_mut().enumerate() {
+        *commitment = hash(&seed(index)).to_bytes();
+    }
+    // Half the pool to full matches unless a test sets its own tiers
+    let mut prize_tiers = [0; 11];
+    prize_tiers[sequence_length as usize] = 5000;
+    InitializeParams {
+        sequence_length,
+        reveal_interval: 60 * 60,
+        ticket_price: TICKET_PRICE,
+        prize_tiers,
+        seed_commitments,
+        settlement_period: 24 * 60 * 60,
+        sales_close_index: 1,
+        price_multipliers: [10000; 10],
+        unique_sequences: false,
+        min_number: 1,
+        max_number: 9,
+        allow_repeats: false,
+        match_mode: MatchMode::OrderedExact,
+    }
+}
+
+impl Env {
+    /// A lottery paid in an SPL Token mint.
+    pub fn new(params: InitializeParams) -> Self {
+        Self::with_mint(params, spl_token::ID, None)
+    }
+
+    /// A lottery paid in a Token-2022 mint with a transfer fee.
+    pub fn with_transfer_fee(params: InitializeParams, fee: TransferFee) -> Self {
+        Self::with_mint(params, spl_token_2022::ID, Some(fee))
+    }
+
+    fn with_mint(params: InitializeParams, token_program: Pubkey, fee: Option<TransferFee>) -> Self {
+        let mut bank = Bank::new();
+        bank.add_program(sol::ID, sol::entry);
+        let authority = bank.new_user();
+        let mint = bank.create_mint(&token_program, &authority, 6, fee);
+
+        let mut env = Self {
+            bank,
+            authority,
+            lottery: Pubkey::default(),
+            mint,
+            token_program,
+        };
+        let accounts = accounts::InitializeRegistry {
+            registry: env.registry(),
+            authority,
+            system_program: system_program::ID,
+        };
+        env.send(accounts, instruction::InitializeRegistry {}, &[authority])
+            .expect("registry initializes");
+        env.lottery = env.initialize(params).expect("lottery initializes");
+        env
+    }
+
+    /// Creates the authority's next lottery against the same mint, returning
+    /// it. `Env` keeps pointing at the first lottery.
+    pub fn initialize(&mut self, params: InitializeParams) -> std::result::Result<Pubkey, ProgramError> {
+        let registry = self.registry();
+        let lottery_id = self.bank.get::<sol::LotteryRegistry>(&registry).lottery_count;
+        let lottery = pda(&[b"lottery", self.authority.as_ref(), &lottery_id.to_le_bytes()]);
+        let accounts = accounts::Initialize {
+            registry,
+            lottery,
+            registry_entry: self.registry_entry(lottery_id),
+            lottery_authority: pda(&[b"lottery", lottery.as_ref()]),
+            lottery_vault: pda(&[b"vault", lottery.as_ref()]),
+            mint: self.mint,
+            authority: self.authority,
+            token_program: self.token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        };
+        let authority = self.authority;
+        self.send(accounts, instruction::Initialize { params }, &[authority])?;
+        Ok(lottery)
+    }
+
+    pub fn send(
+        &mut self,
+        accounts: impl ToAccountMetas,
+        data: impl InstructionData,
+        signers: &[Pubkey],
+    ) -> std::result::Result<(), ProgramError> {
+        self.bank.process(&[ix(accounts, data)], signers)
+    }
+
+    pub fn registry(&self) -> Pubkey {
+        pda(&[b"registry", self.authority.as_ref()])
+    }
+
+    pub fn registry_entry(&self, lottery_id: u64) -> Pubkey {
+        pda(&[b"entry", self.registry().as_ref(), &lottery_id.to_le_bytes()])
+    }
+
+    pub fn lottery_authority(&self) -> Pubkey {
+        pda(&[b"lottery", self.lottery.as_ref()])
+    }
+
+    pub fn vault(&self) -> Pubkey {
+        pda(&[b"vault", self.lottery.as_ref()])
+    }
+
+    pub fn ticket(&self, ticket_id: u64) -> Pubkey {
+        pda(&[b"ticket", self.lottery.as_ref(), &ticket_id.to_le_bytes()])
+    }
+
+    /// The sequence claim for `picked`, derived the way clients are told to:
+    /// sha256 of the numbers, sorted and deduplicated when they play as a set.
+    pub fn sequence_claim(&self, picked: &[u8]) -> Pubkey {
+        let mut key = picked.to_vec();
+        if self.lottery().match_mode == MatchMode::UnorderedSet {
+            key.sort();
+            key.dedup();
+        }
+        pda(&[b"sequence", self.lottery.as_ref(), hash(&key).as_ref()])
+    }
+
+    pub fn lottery(&self) -> sol::Lottery {
+        self.bank.get(&self.lottery)
+    }
+
+    /// A funded player and their token account, holding `amount`.
+    pub fn player(&mut self, amount: u64) -> (Pubkey, Pubkey) {
+        let player = self.bank.new_user();
+        let token_account = self.token_account(&player);
+        let (mint, authority) = (self.mint, self.authority);
+        self.bank.mint_to(&mint, &token_account, &authority, amount);
+        (player, token_account)
+    }
+
+    pub fn token_account(&mut self, owner: &Pubkey) -> Pubkey {
+        let mint = self.mint;
+        self.bank.create_token_account(&mint, owner)
+    }
+
+    /// Buys `picked` for `buyer` at the current price, returning the ticket.
+    pub fn buy(
+        &mut self,
+        buyer: Pubkey,
+        buyer_token_account: Pubkey,
+        picked: &[u8],
+    ) -> std::result::Result<Pubkey, ProgramError> {
+        let mut sequence = [0; 10];
+        sequence[..picked.len()].copy_from_slice(picked);
+        let lottery = self.lottery();
+        let ticket = self.ticket(lottery.next_ticket_id);
+        let accounts = accounts::BuyTicket {
+            lottery: self.lottery,
+            ticket,
+            sequence_claim: lottery.unique_sequences.then(|| self.sequence_claim(picked)),
+            lottery_vault: self.vault(),
+            buyer_token_account,
+            mint: self.mint,
+            buyer,
+            token_program: self.token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        };
+        self.send(accounts, instruction::BuyTicket { sequence }, &[buyer])?;
+        Ok(ticket)
+    }
+
+    /// Reveals the next committed seed once the reveal interval allows it.
+    pub fn reveal(&mut self) -> std::result::Result<(), ProgramError> {
+        let lottery = self.lottery();
+        if lottery.current_reveal_index > 0 {
+            self.bank
+                .warp_to(lottery.last_reveal_timestamp + lottery.reveal_interval);
+        }
+        let accounts = accounts::RevealNumber {
+            lottery: self.lottery,
+            authority: self.authority,
+            proposal: None,
+            system_program: system_program::ID,
+        };
+        let data = instruction::RevealNextNumber {
+            seed: seed(lottery.current_reveal_index as usize),
+        };
+        let authority = self.authority;
+        self.send(accounts, data, &[authority])
+    }
+
+    /// Reveals every number, opening settlement, and returns the draw.
+    pub fn reveal_all(&mut self) -> Vec<u8> {
+        while self.lottery().current_reveal_index < self.lottery().sequence_length {
+            self.reveal().unwrap();
+        }
+        let lottery = self.lottery();
+        lottery.winning_sequence[..lottery.sequence_length as usize].to_vec()
+    }
+
+    pub fn register(&mut self, ticket: Pubkey) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::RegisterWinner {
+            lottery: self.lottery,
+            ticket,
+        };
+        self.send(accounts, instruction::RegisterWinner {}, &[])
+    }
+
+    /// Waits out the settlement window and closes it.
+    pub fn finalize(&mut self) -> std::result::Result<(), ProgramError> {
+        let deadline = self.lottery().settlement_deadline;
+        self.bank.warp_to(deadline);
+        let accounts = accounts::FinalizeSettlement {
+            lottery: self.lottery,
+        };
+        self.send(accounts, instruction::FinalizeSettlement {}, &[])
+    }
+
+    pub fn claim(
+        &mut self,
+        winner: Pubkey,
+        winner_token_account: Pubkey,
+        ticket: Pubkey,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::ClaimPrize {
+            lottery: self.lottery,
+            lottery_authority: self.lottery_authority(),
+            ticket,
+            lottery_vault: self.vault(),
+            winner_token_account,
+            mint: self.mint,
+            winner,
+            token_program: self.token_program,
+            system_program: system_program::ID,
+        };
+        self.send(accounts, instruction::ClaimPrize {}, &[winner])
+    }
+
+    pub fn sweep(&mut self, destination: Pubkey) -> std::result::Result<(), ProgramError> {
+        let authority = self.authority;
+        self.sweep_with(authority, destination, None)
+    }
+
+    /// Sweeps as `caller`, executing `proposal` if there is one.
+    pub fn sweep_with(
+        &mut self,
+        caller: Pubkey,
+        destination: Pubkey,
+        proposal: Option<Pubkey>,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::SweepUnallocated {
+            lottery: self.lottery,
+            lottery_authority: self.lottery_authority(),
+            lottery_vault: self.vault(),
+            destination,
+            mint: self.mint,
+            authority: caller,
+            proposal,
+            token_program: self.token_program,
+        };
+        self.send(accounts, instruction::SweepUnallocated {}, &[caller])
+    }
+
+    pub fn cancel(&mut self, caller: Pubkey) -> std::result::Result<(), ProgramError> {
+        self.cancel_with(caller, None)
+    }
+
+    pub fn cancel_with(
+        &mut self,
+        caller: Pubkey,
+        proposal: Option<Pubkey>,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::CancelLottery {
+            lottery: self.lottery,
+            caller,
+            proposal,
+        };
+        self.send(accounts, instruction::CancelLottery {}, &[caller])
+    }
+
+    pub fn refund(
+        &mut self,
+        owner: Pubkey,
+        owner_token_account: Pubkey,
+        ticket: Pubkey,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::RefundTicket {
+            lottery: self.lottery,
+            lottery_authority: self.lottery_authority(),
+            ticket,
+            lottery_vault: self.vault(),
+            owner_token_account,
+            mint: self.mint,
+            owner,
+            token_program: self.token_program,
+        };
+        self.send(accounts, instruction::RefundTicket {}, &[owner])
+    }
+
+    /// Opens the marketplace with the authority's token account as treasury,
+    /// returning the treasury.
+    pub fn configure_marketplace(
+        &mut self,
+        marketplace_fee_bps: u16,
+    ) -> std::result::Result<Pubkey, ProgramError> {
+        let authority = self.authority;
+        let treasury = self.token_account(&authority);
+        let accounts = accounts::ConfigureMarketplace {
+            lottery: self.lottery,
+            treasury,
+            authority,
+            proposal: None,
+        };
+        let data = instruction::ConfigureMarketplace { marketplace_fee_bps };
+        self.send(accounts, data, &[authority])?;
+        Ok(treasury)
+    }
+
+    pub fn listing(&self, ticket: Pubkey) -> Pubkey {
+        pda(&[b"listing", ticket.as_ref()])
+    }
+
+    pub fn list(&mut self, seller: Pubkey, ticket: Pubkey, price: u64) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::ListTicket {
+            lottery: self.lottery,
+            ticket,
+            listing: self.listing(ticket),
+            seller,
+            system_program: system_program::ID,
+        };
+        self.send(accounts, instruction::ListTicket { price }, &[seller])
+    }
+
+    pub fn delist(&mut self, seller: Pubkey, ticket: Pubkey) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::DelistTicket {
+            ticket,
+            listing: self.listing(ticket),
+            seller,
+        };
+        self.send(accounts, instruction::DelistTicket {}, &[seller])
+    }
+
+    pub fn purchase(
+        &mut self,
+        buyer: Pubkey,
+        buyer_token_account: Pubkey,
+        ticket: Pubkey,
+        seller_token_account: Pubkey,
+    ) -> std::result::Result<(), ProgramError> {
+        let listing = self.listing(ticket);
+        let accounts = accounts::PurchaseListedTicket {
+            lottery: self.lottery,
+            ticket,
+            listing,
+            buyer_token_account,
+            seller_token_account,
+            treasury: self.lottery().treasury,
+            mint: self.mint,
+            seller: self.bank.get::<sol::Listing>(&listing).seller,
+            buyer,
+            token_program: self.token_program,
+        };
+        self.send(accounts, instruction::PurchaseListedTicket {}, &[buyer])
+    }
+
+    pub fn offer(&self, buyer: Pubkey, nonce: u64) -> Pubkey {
+        pda(&[b"offer", buyer.as_ref(), &nonce.to_le_bytes()])
+    }
+
+    pub fn escrow(&self, offer: Pubkey) -> Pubkey {
+        pda(&[b"offer_escrow", offer.as_ref()])
+    }
+
+    /// Escrows `amount` from `buyer_token_account` for `target`, returning
+    /// the offer.
+    pub fn place_offer(
+        &mut self,
+        buyer: Pubkey,
+        buyer_token_account: Pubkey,
+        nonce: u64,
+        target: OfferTarget,
+        amount: u64,
+        expires_at: i64,
+    ) -> std::result::Result<Pubkey, ProgramError> {
+        let offer = self.offer(buyer, nonce);
+        let accounts = accounts::PlaceOffer {
+            lottery: self.lottery,
+            offer,
+            escrow: self.escrow(offer),
+            buyer_token_account,
+            mint: self.mint,
+            buyer,
+            token_program: self.token_program,
+            system_program: system_program::ID,
+        };
+        let data = instruction::PlaceOffer {
+            nonce,
+            target,
+            amount,
+            expires_at,
+        };
+        self.send(accounts, data, &[buyer])?;
+        Ok(offer)
+    }
+
+    pub fn cancel_offer(
+        &mut self,
+        caller: Pubkey,
+        offer: Pubkey,
+        buyer_token_account: Pubkey,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::CancelOffer {
+            lottery: self.lottery,
+            offer,
+            escrow: self.escrow(offer),
+            buyer_token_account,
+            mint: self.mint,
+            buyer: self.bank.get::<sol::Offer>(&offer).buyer,
+            caller,
+            token_program: self.token_program,
+        };
+        self.send(accounts, instruction::CancelOffer {}, &[caller])
+    }
+
+    pub fn accept_offer(
+        &mut self,
+        seller: Pubkey,
+        seller_token_account: Pubkey,
+        ticket: Pubkey,
+        offer: Pubkey,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::AcceptOffer {
+            lottery: self.lottery,
+            ticket,
+            offer,
+            escrow: self.escrow(offer),
+            seller_token_account,
+            treasury: self.lottery().treasury,
+            mint: self.mint,
+            buyer: self.bank.get::<sol::Offer>(&offer).buyer,
+            seller,
+            token_program: self.token_program,
+        };
+        self.send(accounts, instruction::AcceptOffer {}, &[seller])
+    }
+
+    pub fn proposal(&self, id: u64) -> Pubkey {
+        pda(&[b"proposal", self.lottery.as_ref(), &id.to_le_bytes()])
+    }
+
+    /// Replaces the approver set as `caller`, executing `proposal` if there is
+    /// one.
+    pub fn set_approvers(
+        &mut self,
+        caller: Pubkey,
+        approvers: Vec<Pubkey>,
+        threshold: u8,
+        proposal: Option<Pubkey>,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::SetApprovers {
+            lottery: self.lottery,
+            authority: caller,
+            proposal,
+        };
+        let data = instruction::SetApprovers {
+            approvers,
+            threshold,
+        };
+        self.send(accounts, data, &[caller])
+    }
+
+    /// Proposes `action` as `approver`, returning the proposal.
+    pub fn create_proposal(
+        &mut self,
+        approver: Pubkey,
+        action: ProposalAction,
+    ) -> std::result::Result<Pubkey, ProgramError> {
+        let proposal = self.proposal(self.lottery().proposal_count);
+        let accounts = accounts::CreateProposal {
+            lottery: self.lottery,
+            proposal,
+            approver,
+            system_program: system_program::ID,
+        };
+        self.send(accounts, instruction::CreateProposal { action }, &[approver])?;
+        Ok(proposal)
+    }
+
+    pub fn approve(&mut self, approver: Pubkey, proposal: Pubkey) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::ApproveProposal {
+            lottery: self.lottery,
+            proposal,
+            approver,
+        };
+        self.send(accounts, instruction::ApproveProposal {}, &[approver])
+    }
+
+    pub fn set_paused(
+        &mut self,
+        caller: Pubkey,
+        paused: u8,
+        proposal: Option<Pubkey>,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = accounts::SetPaused {
+            lottery: self.lottery,
+            authority: caller,
+            proposal,
+        };
+        self.send(accounts, instruction::SetPaused { paused }, &[caller])
+    }
+}
+
+pub fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &sol::ID).0
+}
+
+pub fn ix(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: sol::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// What a failed `require!` with `error` comes back as.
+pub fn error(error: sol::LotteryError) -> ProgramError {
+    ProgramError::Custom(error.into())
+}
+
+/// What creating an account that already exists fails with: the system
+/// program's AccountAlreadyInUse.
+pub fn already_in_use() -> ProgramError {
+    ProgramError::Custom(0)
+}
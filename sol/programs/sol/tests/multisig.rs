@@ -0,0 +1,164 @@
+//! A lottery's approver set: once it has a threshold, privileged operations
+//! run only through proposals enough approvers have signed off.
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::hash::hashv;
+use common::{error, params, Env, TICKET_PRICE};
+use sol::{LotteryError, LotteryState, Proposal, ProposalAction, PAUSE_BUY};
+
+// A lottery whose three approvers need two of them to agree
+fn two_of_three() -> (Env, [Pubkey; 3]) {
+    let mut env = Env::new(params(3));
+    let approvers = [env.bank.new_user(), env.bank.new_user(), env.bank.new_user()];
+    let authority = env.authority;
+    env.set_approvers(authority, approvers.to_vec(), 2, None).unwrap();
+    (env, approvers)
+}
+
+#[test]
+fn the_authority_acts_alone_until_there_is_a_threshold() {
+    let mut env = Env::new(params(3));
+    let authority = env.authority;
+    let approver = env.bank.new_user();
+    assert_eq!(
+        env.create_proposal(approver, ProposalAction::CancelLottery),
+        Err(error(LotteryError::MultisigDisabled))
+    );
+    assert_eq!(
+        env.set_approvers(authority, vec![approver, approver], 1, None),
+        Err(error(LotteryError::InvalidApprovers))
+    );
+    env.set_paused(authority, PAUSE_BUY, None).unwrap();
+
+    env.set_approvers(authority, vec![approver], 1, None).unwrap();
+    assert_eq!(
+        env.set_paused(authority, 0, None),
+        Err(error(LotteryError::ProposalRequired))
+    );
+    assert_eq!(env.lottery().paused, PAUSE_BUY);
+}
+
+#[test]
+fn proposals_execute_once_the_threshold_is_met() {
+    let (mut env, [first, second, _]) = two_of_three();
+    let proposal = env
+        .create_proposal(first, ProposalAction::SetPaused { paused: PAUSE_BUY })
+        .unwrap();
+
+    // The proposer's approval alone isn't enough
+    let authority = env.authority;
+    assert_eq!(
+        env.set_paused(authority, PAUSE_BUY, Some(proposal)),
+        Err(error(LotteryError::ThresholdNotMet))
+    );
+    assert_eq!(env.approve(first, proposal), Err(error(LotteryError::AlreadyApproved)));
+    let stranger = env.bank.new_user();
+    assert_eq!(env.approve(stranger, proposal), Err(error(LotteryError::NotApprover)));
+    env.approve(second, proposal).unwrap();
+
+    // Whoever submits it, it runs once
+    env.set_paused(stranger, PAUSE_BUY, Some(proposal)).unwrap();
+    assert_eq!(env.lottery().paused, PAUSE_BUY);
+    assert!(env.bank.get::<Proposal>(&proposal).executed);
+    assert_eq!(
+        env.set_paused(stranger, PAUSE_BUY, Some(proposal)),
+        Err(error(LotteryError::ProposalAlreadyExecuted))
+    );
+}
+
+#[test]
+fn a_proposal_only_runs_the_action_it_names() {
+    let (mut env, [first, second, _]) = two_of_three();
+    let proposal = env
+        .create_proposal(first, ProposalAction::SetPaused { paused: PAUSE_BUY })
+        .unwrap();
+    env.approve(second, proposal).unwrap();
+
+    let authority = env.authority;
+    assert_eq!(
+        env.set_paused(authority, 0, Some(proposal)),
+        Err(error(LotteryError::ProposalMismatch))
+    );
+    assert_eq!(
+        env.cancel_with(authority, Some(proposal)),
+        Err(error(LotteryError::ProposalMismatch))
+    );
+
+    // Nor on another lottery, even one with the same approvers
+    let other = env.initialize(params(3)).unwrap();
+    let accounts = sol::accounts::SetApprovers {
+        lottery: other,
+        authority,
+        proposal: None,
+    };
+    let data = sol::instruction::SetApprovers {
+        approvers: vec![first, second],
+        threshold: 2,
+    };
+    env.send(accounts, data, &[authority]).unwrap();
+    let accounts = sol::accounts::SetPaused {
+        lottery: other,
+        authority,
+        proposal: Some(proposal),
+    };
+    assert_eq!(
+        env.send(accounts, sol::instruction::SetPaused { paused: PAUSE_BUY }, &[authority]),
+        Err(error(LotteryError::ProposalMismatch))
+    );
+}
+
+#[test]
+fn a_new_approver_set_voids_open_proposals() {
+    let (mut env, [first, second, third]) = two_of_three();
+    let open = env
+        .create_proposal(first, ProposalAction::SetPaused { paused: PAUSE_BUY })
+        .unwrap();
+
+    // Dropping the third approver goes through the multisig too
+    let approvers = vec![first, second];
+    let keys: Vec<&[u8]> = approvers.iter().map(|key| key.as_ref()).collect();
+    let action = ProposalAction::SetApprovers {
+        approvers_hash: hashv(&keys).to_bytes(),
+        threshold: 2,
+    };
+    let change = env.create_proposal(second, action).unwrap();
+    env.approve(third, change).unwrap();
+    let authority = env.authority;
+    env.set_approvers(authority, approvers, 2, Some(change)).unwrap();
+
+    assert_eq!(env.approve(second, open), Err(error(LotteryError::StaleProposal)));
+    assert_eq!(
+        env.create_proposal(third, ProposalAction::CancelLottery),
+        Err(error(LotteryError::NotApprover))
+    );
+    assert_eq!(
+        env.set_paused(authority, PAUSE_BUY, Some(open)),
+        Err(error(LotteryError::StaleProposal))
+    );
+}
+
+#[test]
+fn approvers_cancel_before_reveals_stall() {
+    let (mut env, [first, second, _]) = two_of_three();
+    let (player, token_account) = env.player(TICKET_PRICE);
+    let ticket = env.buy(player, token_account, &[1, 2, 3]).unwrap();
+
+    // Until reveals stall, cancelling takes a proposal like anything else the
+    // authority could do
+    let stranger = env.bank.new_user();
+    assert_eq!(env.cancel(stranger), Err(error(LotteryError::RevealsNotStalled)));
+    let authority = env.authority;
+    assert_eq!(env.cancel(authority), Err(error(LotteryError::ProposalRequired)));
+
+    let proposal = env.create_proposal(first, ProposalAction::CancelLottery).unwrap();
+    assert_eq!(
+        env.cancel_with(stranger, Some(proposal)),
+        Err(error(LotteryError::ThresholdNotMet))
+    );
+    env.approve(second, proposal).unwrap();
+    env.cancel_with(stranger, Some(proposal)).unwrap();
+    assert!(env.lottery().state == LotteryState::Cancelled);
+    env.refund(player, token_account, ticket).unwrap();
+}
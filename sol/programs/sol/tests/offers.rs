@@ -0,0 +1,155 @@
+//! Offers on tickets: the bid sits in an escrow token account until the
+//! ticket's owner accepts it, the buyer cancels it or it lapses.
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use common::{error, params, Env, TICKET_PRICE};
+use sol::{LotteryError, OfferTarget, Ticket};
+
+const FEE_BPS: u16 = 500;
+const BID: u64 = 2 * TICKET_PRICE;
+
+fn marketplace() -> (Env, Pubkey) {
+    let mut env = Env::new(params(3));
+    let treasury = env.configure_marketplace(FEE_BPS).unwrap();
+    (env, treasury)
+}
+
+fn in_an_hour(env: &Env) -> i64 {
+    env.bank.clock().unix_timestamp + 60 * 60
+}
+
+#[test]
+fn offers_wait_for_the_marketplace() {
+    let mut env = Env::new(params(3));
+    let (buyer, buyer_token_account) = env.player(BID);
+    let expires_at = in_an_hour(&env);
+    let target = OfferTarget::Ticket { ticket_id: 1 };
+    assert_eq!(
+        env.place_offer(buyer, buyer_token_account, 0, target, BID, expires_at),
+        Err(error(LotteryError::MarketplaceNotConfigured))
+    );
+}
+
+#[test]
+fn accepted_offers_pay_the_seller_less_the_marketplace_fee() {
+    let (mut env, treasury) = marketplace();
+    let (seller, seller_token_account) = env.player(TICKET_PRICE);
+    let ticket = env.buy(seller, seller_token_account, &[1, 2, 3]).unwrap();
+
+    let (buyer, buyer_token_account) = env.player(BID);
+    let lamports = env.bank.lamports(&buyer);
+    let expires_at = in_an_hour(&env);
+    let ticket_id = env.bank.get::<Ticket>(&ticket).ticket_id;
+    let target = OfferTarget::Ticket { ticket_id };
+    let offer = env
+        .place_offer(buyer, buyer_token_account, 0, target, BID, expires_at)
+        .unwrap();
+    assert_eq!(env.bank.token_balance(&env.escrow(offer)), BID);
+    assert_eq!(env.bank.token_balance(&buyer_token_account), 0);
+
+    // Only the ticket's owner can accept
+    let (other, other_token_account) = env.player(0);
+    assert_eq!(
+        env.accept_offer(other, other_token_account, ticket, offer),
+        Err(error(LotteryError::NotTicketOwner))
+    );
+    env.accept_offer(seller, seller_token_account, ticket, offer).unwrap();
+
+    let fee = BID * FEE_BPS as u64 / 10000;
+    assert_eq!(env.bank.token_balance(&seller_token_account), BID - fee);
+    assert_eq!(env.bank.token_balance(&treasury), fee);
+    assert_eq!(env.bank.get::<Ticket>(&ticket).owner, buyer);
+
+    // Both accounts are closed and their rent is back with the buyer
+    assert!(env.bank.account(&offer).is_none());
+    assert!(env.bank.account(&env.escrow(offer)).is_none());
+    assert_eq!(env.bank.lamports(&buyer), lamports);
+}
+
+#[test]
+fn prefix_offers_take_any_ticket_matching_the_draw() {
+    let (mut env, _) = marketplace();
+    // One ticket starting with each number, so exactly one matches the first
+    // reveal whatever it is
+    let mut tickets = vec![];
+    for first in 1..=9u8 {
+        let picked = [first, first % 9 + 1, (first + 1) % 9 + 1];
+        let (player, token_account) = env.player(TICKET_PRICE);
+        tickets.push((player, token_account, env.buy(player, token_account, &picked).unwrap()));
+    }
+    let (buyer, buyer_token_account) = env.player(BID);
+    let expires_at = in_an_hour(&env);
+    let prefix = |length| OfferTarget::Prefix { length };
+    assert_eq!(
+        env.place_offer(buyer, buyer_token_account, 0, prefix(1), BID, expires_at),
+        Err(error(LotteryError::InvalidOffer))
+    );
+
+    env.reveal().unwrap();
+    let drawn = env.lottery().winning_sequence[0];
+    // Nothing past the last reveal
+    assert_eq!(
+        env.place_offer(buyer, buyer_token_account, 0, prefix(2), BID, expires_at),
+        Err(error(LotteryError::InvalidOffer))
+    );
+    let offer = env
+        .place_offer(buyer, buyer_token_account, 0, prefix(1), BID, expires_at)
+        .unwrap();
+
+    let (seller, seller_token_account, ticket) = tickets.remove(drawn as usize - 1);
+    let (other, other_token_account, other_ticket) = tickets[0];
+    assert_eq!(
+        env.accept_offer(other, other_token_account, other_ticket, offer),
+        Err(error(LotteryError::OfferMismatch))
+    );
+    env.accept_offer(seller, seller_token_account, ticket, offer).unwrap();
+    assert_eq!(env.bank.get::<Ticket>(&ticket).owner, buyer);
+}
+
+#[test]
+fn buyers_cancel_and_anyone_clears_lapsed_offers() {
+    let (mut env, _) = marketplace();
+    let (seller, seller_token_account) = env.player(TICKET_PRICE);
+    let ticket = env.buy(seller, seller_token_account, &[1, 2, 3]).unwrap();
+    let (buyer, buyer_token_account) = env.player(BID);
+    let stranger = env.bank.new_user();
+    let expires_at = in_an_hour(&env);
+    let ticket_id = env.bank.get::<Ticket>(&ticket).ticket_id;
+    let target = OfferTarget::Ticket { ticket_id };
+
+    // Only the buyer while it could still be accepted
+    let offer = env
+        .place_offer(buyer, buyer_token_account, 0, target.clone(), BID, expires_at)
+        .unwrap();
+    assert_eq!(
+        env.cancel_offer(stranger, offer, buyer_token_account),
+        Err(error(LotteryError::OfferStillActive))
+    );
+    env.cancel_offer(buyer, offer, buyer_token_account).unwrap();
+    assert_eq!(env.bank.token_balance(&buyer_token_account), BID);
+    assert!(env.bank.account(&env.escrow(offer)).is_none());
+
+    // Anyone once it's expired
+    let offer = env
+        .place_offer(buyer, buyer_token_account, 1, target.clone(), BID, expires_at)
+        .unwrap();
+    env.bank.warp_to(expires_at);
+    assert_eq!(
+        env.accept_offer(seller, seller_token_account, ticket, offer),
+        Err(error(LotteryError::OfferExpired))
+    );
+    env.cancel_offer(stranger, offer, buyer_token_account).unwrap();
+    assert_eq!(env.bank.token_balance(&buyer_token_account), BID);
+
+    // Or once the lottery stops trading
+    let expires_at = in_an_hour(&env);
+    let offer = env
+        .place_offer(buyer, buyer_token_account, 2, target, BID, expires_at)
+        .unwrap();
+    let authority = env.authority;
+    env.cancel(authority).unwrap();
+    env.cancel_offer(stranger, offer, buyer_token_account).unwrap();
+    assert_eq!(env.bank.token_balance(&buyer_token_account), BID);
+}
@@ -0,0 +1,61 @@
+//! The authority's registry of lotteries, one entry per lottery id.
+
+mod common;
+
+use anchor_lang::error::ErrorCode as AnchorError;
+use anchor_lang::prelude::*;
+use common::{params, Env};
+use sol::{accounts, instruction, LotteryRegistry, RegistryEntry};
+
+#[test]
+fn every_lottery_gets_an_entry() {
+    let mut env = Env::new(params(3));
+    let second = env.initialize(params(6)).unwrap();
+
+    let registry: LotteryRegistry = env.bank.get(&env.registry());
+    assert_eq!(registry.lottery_count, 2);
+    for (lottery_id, lottery) in [env.lottery, second].into_iter().enumerate() {
+        let entry: RegistryEntry = env.bank.get(&env.registry_entry(lottery_id as u64));
+        assert_eq!(entry.lottery, lottery);
+        assert_eq!(entry.authority, env.authority);
+        assert_eq!(env.bank.get::<sol::Lottery>(&lottery).lottery_id, lottery_id as u64);
+    }
+}
+
+#[test]
+fn entry_follows_an_authority_transfer() {
+    let mut env = Env::new(params(3));
+    let second = env.initialize(params(3)).unwrap();
+    let new_authority = env.bank.new_user();
+
+    let accounts = accounts::ProposeAuthority {
+        lottery: second,
+        authority: env.authority,
+        proposal: None,
+    };
+    let authority = env.authority;
+    env.send(accounts, instruction::ProposeAuthority { new_authority }, &[authority])
+        .unwrap();
+
+    // Only the second lottery's own entry will do
+    let accept = |registry_entry| accounts::AcceptAuthority {
+        lottery: second,
+        registry_entry,
+        new_authority,
+    };
+    let result = env.send(
+        accept(env.registry_entry(0)),
+        instruction::AcceptAuthority {},
+        &[new_authority],
+    );
+    assert_eq!(result, Err(ProgramError::Custom(AnchorError::ConstraintSeeds.into())));
+    env.send(accept(env.registry_entry(1)), instruction::AcceptAuthority {}, &[new_authority])
+        .unwrap();
+
+    let entry: RegistryEntry = env.bank.get(&env.registry_entry(1));
+    assert_eq!(entry.lottery, second);
+    assert_eq!(entry.authority, new_authority);
+    assert_eq!(env.bank.get::<sol::Lottery>(&second).authority, new_authority);
+    // The first lottery and its entry are untouched
+    assert_eq!(env.bank.get::<RegistryEntry>(&env.registry_entry(0)).authority, env.authority);
+}
@@ -0,0 +1,176 @@
+//! Registering winners, settling and paying prize tiers out of the vault.
+
+mod common;
+
+use common::{error, params, Env, TICKET_PRICE};
+use sol::{LotteryError, MatchMode};
+
+// Two numbers drawn from 1..=3 in any order, so the draw leaves out exactly
+// one number: whatever it is, the ticket for the other two matches both and
+// the rest match one each
+const SETS: [[u8; 2]; 3] = [[1, 2], [1, 3], [2, 3]];
+
+fn unordered_pairs() -> Env {
+    let mut params = params(2);
+    params.max_number = 3;
+    params.match_mode = MatchMode::UnorderedSet;
+    params.prize_tiers[1] = 2000;
+    params.prize_tiers[2] = 5000;
+    Env::new(params)
+}
+
+fn matches(picked: &[u8], drawn: &[u8]) -> usize {
+    picked.iter().filter(|number| drawn.contains(number)).count()
+}
+
+#[test]
+fn claims_pay_the_tier_for_the_match_count() {
+    let mut env = unordered_pairs();
+    let mut tickets = vec![];
+    for set in SETS {
+        let (player, token_account) = env.player(TICKET_PRICE);
+        tickets.push((player, token_account, env.buy(player, token_account, &set).unwrap(), set));
+    }
+
+    let drawn = env.reveal_all();
+    for (.., ticket, _) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+
+    let pool = 3 * TICKET_PRICE;
+    for (player, token_account, ticket, set) in tickets {
+        env.claim(player, token_account, ticket).unwrap();
+        let expected = match matches(&set, &drawn) {
+            // The whole 50% tier
+            2 => pool / 2,
+            // The 20% tier, shared by the two tickets matching one number
+            _ => pool / 5 / 2,
+        };
+        assert_eq!(env.bank.token_balance(&token_account), expected);
+        assert!(env.bank.get::<sol::Ticket>(&ticket).claimed);
+    }
+    assert_eq!(env.lottery().total_paid, pool / 2 + pool / 5);
+    assert_eq!(env.bank.token_balance(&env.vault()), pool - pool / 2 - pool / 5);
+}
+
+#[test]
+fn claims_wait_for_settlement() {
+    let mut env = unordered_pairs();
+    let (player, token_account) = env.player(TICKET_PRICE);
+    let ticket = env.buy(player, token_account, &[1, 2]).unwrap();
+
+    // Not while numbers are still being revealed, nor before it's settled
+    assert_eq!(env.register(ticket), Err(error(LotteryError::NotSettling)));
+    env.reveal_all();
+    env.register(ticket).unwrap();
+    assert_eq!(
+        env.claim(player, token_account, ticket),
+        Err(error(LotteryError::LotteryNotCompleted))
+    );
+    assert_eq!(env.finalize(), Ok(()));
+    env.claim(player, token_account, ticket).unwrap();
+}
+
+#[test]
+fn registered_winners_split_their_tier_and_the_rest_is_swept() {
+    let mut env = unordered_pairs();
+    let mut tickets = vec![];
+    for set in SETS.iter().chain(&SETS) {
+        let (player, token_account) = env.player(TICKET_PRICE);
+        tickets.push((player, token_account, env.buy(player, token_account, set).unwrap(), *set));
+    }
+    let pool = 6 * TICKET_PRICE;
+
+    // Two tickets match both numbers and four match one; one of those four
+    // never registers
+    let drawn = env.reveal_all();
+    let one_match = tickets
+        .iter()
+        .position(|(.., set)| matches(set, &drawn) == 1)
+        .unwrap();
+    let (player, token_account, unregistered, _) = tickets.remove(one_match);
+    for (.., ticket, _) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+    assert_eq!(env.register(unregistered), Err(error(LotteryError::NotSettling)));
+
+    // Sweeping first leaves every registered share in the vault
+    let someone = env.bank.new_user();
+    let accounts = sol::accounts::SweepUnallocated {
+        lottery: env.lottery,
+        lottery_authority: env.lottery_authority(),
+        lottery_vault: env.vault(),
+        destination: env.token_account(&someone),
+        mint: env.mint,
+        authority: someone,
+        proposal: None,
+        token_program: env.token_program,
+    };
+    let result = env.send(accounts, sol::instruction::SweepUnallocated {}, &[someone]);
+    assert_eq!(result, Err(error(LotteryError::Unauthorized)));
+
+    let authority = env.authority;
+    let treasury = env.token_account(&authority);
+    env.sweep(treasury).unwrap();
+    let allocated = pool / 2 + pool / 5;
+    assert_eq!(env.bank.token_balance(&treasury), pool - allocated);
+    assert_eq!(env.sweep(treasury), Err(error(LotteryError::NothingToSweep)));
+
+    for (player, token_account, ticket, set) in tickets {
+        env.claim(player, token_account, ticket).unwrap();
+        let expected = match matches(&set, &drawn) {
+            2 => pool / 2 / 2,
+            _ => pool / 5 / 3,
+        };
+        assert_eq!(env.bank.token_balance(&token_account), expected);
+        assert_eq!(
+            env.claim(player, token_account, ticket),
+            Err(error(LotteryError::AlreadyClaimed))
+        );
+    }
+    assert_eq!(
+        env.claim(player, token_account, unregistered),
+        Err(error(LotteryError::TicketNotRegistered))
+    );
+
+    // Everything the tickets paid is accounted for
+    assert_eq!(env.lottery().total_paid, pool);
+    assert_eq!(env.bank.token_balance(&env.vault()), 0);
+}
+
+#[test]
+fn listings_lapse_when_the_draw_ends() {
+    let mut env = unordered_pairs();
+    env.configure_marketplace(0).unwrap();
+    let mut tickets = vec![];
+    for set in SETS {
+        let (player, token_account) = env.player(TICKET_PRICE);
+        let ticket = env.buy(player, token_account, &set).unwrap();
+        env.list(player, ticket, TICKET_PRICE).unwrap();
+        tickets.push((player, token_account, ticket));
+    }
+
+    // Still listed when the last number comes out, but nobody can buy them now
+    env.reveal_all();
+    let (buyer, buyer_token_account) = env.player(TICKET_PRICE);
+    let (_, seller_token_account, ticket) = tickets[0];
+    assert_eq!(
+        env.purchase(buyer, buyer_token_account, ticket, seller_token_account),
+        Err(error(LotteryError::LotteryClosed))
+    );
+    for (.., ticket) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+
+    // So the listings don't hold up claims, and sellers can still take back
+    // their rent
+    for (player, token_account, ticket) in tickets {
+        env.claim(player, token_account, ticket).unwrap();
+        assert!(env.bank.token_balance(&token_account) > 0);
+        env.delist(player, ticket).unwrap();
+        assert!(env.bank.account(&env.listing(ticket)).is_none());
+    }
+}
@@ -0,0 +1,125 @@
+//! A Token-2022 mint with a transfer fee: the vault and the lottery's books
+//! only count what actually arrives, and payouts bear their own fee.
+
+mod common;
+
+use common::{params, Env, TICKET_PRICE};
+use program_harness::TransferFee;
+use sol::{MatchMode, Offer, OfferTarget, Ticket};
+
+const FEE_BPS: u16 = 100;
+
+// What the mint withholds on a transfer of `amount`, rounded up like Token-2022
+fn fee(amount: u64) -> u64 {
+    (amount * FEE_BPS as u64).div_ceil(10000)
+}
+
+fn fee_bearing_lottery() -> Env {
+    let mut params = params(2);
+    params.max_number = 3;
+    params.match_mode = MatchMode::UnorderedSet;
+    params.prize_tiers[1] = 2000;
+    params.prize_tiers[2] = 5000;
+    let fee = TransferFee {
+        basis_points: FEE_BPS,
+        maximum: u64::MAX,
+    };
+    Env::with_transfer_fee(params, fee)
+}
+
+#[test]
+fn sales_and_prizes_account_for_the_transfer_fee() {
+    let mut env = fee_bearing_lottery();
+    let received = TICKET_PRICE - fee(TICKET_PRICE);
+    let mut tickets = vec![];
+    for set in [[1, 2], [1, 3], [2, 3]] {
+        let (player, token_account) = env.player(TICKET_PRICE);
+        let ticket = env.buy(player, token_account, &set).unwrap();
+        assert_eq!(env.bank.token_balance(&token_account), 0);
+        assert_eq!(env.bank.get::<Ticket>(&ticket).amount_paid, received);
+        tickets.push((player, token_account, ticket, set));
+    }
+
+    // The pool is what reached the vault; the fees sit withheld beside it
+    let pool = 3 * received;
+    assert_eq!(env.lottery().prize_pool, pool);
+    assert_eq!(env.bank.token_balance(&env.vault()), pool);
+    assert_eq!(env.bank.withheld_in_account(&env.vault()), 3 * fee(TICKET_PRICE));
+
+    let drawn = env.reveal_all();
+    for (.., ticket, _) in &tickets {
+        env.register(*ticket).unwrap();
+    }
+    env.finalize().unwrap();
+
+    // Each prize leaves the vault in full and the winner bears its fee
+    let mut paid = 0;
+    for (player, token_account, ticket, set) in tickets {
+        env.claim(player, token_account, ticket).unwrap();
+        let prize = match set.iter().filter(|number| drawn.contains(number)).count() {
+            2 => pool * 5000 / 10000,
+            _ => pool * 2000 / 10000 / 2,
+        };
+        paid += prize;
+        assert_eq!(env.bank.token_balance(&token_account), prize - fee(prize));
+    }
+    assert_eq!(env.lottery().total_paid, paid);
+    assert_eq!(env.bank.token_balance(&env.vault()), pool - paid);
+}
+
+#[test]
+fn refunds_return_what_the_vault_received() {
+    let mut env = fee_bearing_lottery();
+    let (player, token_account) = env.player(TICKET_PRICE);
+    let ticket = env.buy(player, token_account, &[1, 2]).unwrap();
+
+    let authority = env.authority;
+    env.cancel(authority).unwrap();
+    env.refund(player, token_account, ticket).unwrap();
+
+    // The vault pays out exactly what it took in, so it ends empty; the
+    // player loses the fee on the way in and on the way out
+    let received = TICKET_PRICE - fee(TICKET_PRICE);
+    assert_eq!(env.bank.token_balance(&env.vault()), 0);
+    assert_eq!(env.lottery().total_paid, env.lottery().prize_pool);
+    assert_eq!(env.bank.token_balance(&token_account), received - fee(received));
+}
+
+#[test]
+fn offer_escrows_hand_their_withheld_fees_to_the_mint_on_close() {
+    let mut env = fee_bearing_lottery();
+    env.configure_marketplace(0).unwrap();
+    let (seller, seller_token_account) = env.player(TICKET_PRICE);
+    let ticket = env.buy(seller, seller_token_account, &[1, 2]).unwrap();
+    let (buyer, buyer_token_account) = env.player(2 * TICKET_PRICE);
+    let expires_at = env.bank.clock().unix_timestamp + 60 * 60;
+    let ticket_id = env.bank.get::<Ticket>(&ticket).ticket_id;
+    let target = OfferTarget::Ticket { ticket_id };
+
+    // The offer is for what the escrow can pay out
+    let offer = env
+        .place_offer(buyer, buyer_token_account, 0, target.clone(), TICKET_PRICE, expires_at)
+        .unwrap();
+    let escrowed = TICKET_PRICE - fee(TICKET_PRICE);
+    assert_eq!(env.bank.get::<Offer>(&offer).amount, escrowed);
+    assert_eq!(env.bank.withheld_in_account(&env.escrow(offer)), fee(TICKET_PRICE));
+
+    // Closing the escrow on acceptance first harvests its fee to the mint
+    let seller_balance = env.bank.token_balance(&seller_token_account);
+    env.accept_offer(seller, seller_token_account, ticket, offer).unwrap();
+    assert!(env.bank.account(&env.escrow(offer)).is_none());
+    assert_eq!(env.bank.withheld_in_mint(&env.mint), fee(TICKET_PRICE));
+    assert_eq!(
+        env.bank.token_balance(&seller_token_account) - seller_balance,
+        escrowed - fee(escrowed)
+    );
+
+    // And so does a cancellation
+    let offer = env
+        .place_offer(buyer, buyer_token_account, 1, target, TICKET_PRICE, expires_at)
+        .unwrap();
+    env.cancel_offer(buyer, offer, buyer_token_account).unwrap();
+    assert!(env.bank.account(&env.escrow(offer)).is_none());
+    assert_eq!(env.bank.withheld_in_mint(&env.mint), 2 * fee(TICKET_PRICE));
+    assert_eq!(env.bank.token_balance(&buyer_token_account), escrowed - fee(escrowed));
+}
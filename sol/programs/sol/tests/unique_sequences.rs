@@ -0,0 +1,36 @@
+//! Lotteries that sell each sequence once, keyed by how tickets are scored.
+
+mod common;
+
+use common::{already_in_use, params, Env, TICKET_PRICE};
+use sol::MatchMode;
+
+fn unique(match_mode: MatchMode) -> Env {
+    let mut params = params(3);
+    params.unique_sequences = true;
+    params.match_mode = match_mode;
+    Env::new(params)
+}
+
+#[test]
+fn a_permuted_set_is_a_duplicate() {
+    let mut env = unique(MatchMode::UnorderedSet);
+    let (player, token_account) = env.player(3 * TICKET_PRICE);
+    env.buy(player, token_account, &[3, 1, 2]).unwrap();
+
+    // Same numbers in another order score the same, so the claim exists
+    assert_eq!(env.buy(player, token_account, &[2, 3, 1]), Err(already_in_use()));
+    assert!(env.bank.account(&env.ticket(2)).is_none());
+    env.buy(player, token_account, &[1, 2, 4]).unwrap();
+}
+
+#[test]
+fn ordered_modes_only_reject_the_same_order() {
+    for match_mode in [MatchMode::OrderedExact, MatchMode::OrderedPrefix] {
+        let mut env = unique(match_mode);
+        let (player, token_account) = env.player(3 * TICKET_PRICE);
+        env.buy(player, token_account, &[3, 1, 2]).unwrap();
+        env.buy(player, token_account, &[2, 3, 1]).unwrap();
+        assert_eq!(env.buy(player, token_account, &[3, 1, 2]), Err(already_in_use()));
+    }
+}
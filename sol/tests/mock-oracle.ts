@@ -0,0 +1,226 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import {
+  Ed25519Program,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  TransactionInstruction,
+} from "@solana/web3.js";
+import { createMint, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import { Sol } from "../target/types/sol";
+import { MockOracle } from "../target/types/mock_oracle";
+
+// request_reveal, then an ed25519 verify instruction followed by
+// mock_oracle::fulfill in one transaction
+describe("mock-oracle", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.sol as Program<Sol>;
+  const oracleProgram = anchor.workspace.mockOracle as Program<MockOracle>;
+
+  // A fresh authority so this doesn't collide with other suites' registries
+  const authority = Keypair.generate();
+  const oracleSigner = Keypair.generate();
+
+  const [registry] = PublicKey.findProgramAddressSync(
+    [Buffer.from("registry"), authority.publicKey.toBuffer()],
+    program.programId
+  );
+  const [registryEntry] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("entry"),
+      registry.toBuffer(),
+      new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+  const [lottery] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("lottery"),
+      authority.publicKey.toBuffer(),
+      new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+  const [revealRequest] = PublicKey.findProgramAddressSync(
+    [Buffer.from("reveal_request"), lottery.toBuffer(), Buffer.from([0])],
+    program.programId
+  );
+  const [oracleAuthority] = PublicKey.findProgramAddressSync(
+    [Buffer.from("oracle")],
+    oracleProgram.programId
+  );
+
+  // The ed25519 program lays out header, pubkey, signature, message
+  const signatureOf = (ix: TransactionInstruction) =>
+    Array.from(ix.data.subarray(48, 112));
+
+  const signSeed = (signer: Keypair, message: Uint8Array) => {
+    const ix = Ed25519Program.createInstructionWithPrivateKey({
+      privateKey: signer.secretKey,
+      message,
+    });
+    return { ix, proof: signatureOf(ix) };
+  };
+
+  const fulfill = (proof: number[]) =>
+    oracleProgram.methods.fulfill(proof).accountsPartial({
+      oracleAuthority,
+      lottery,
+      revealRequest,
+      instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+      lotteryProgram: program.programId,
+    });
+
+  const expectRejection = async (
+    attempt: Promise<unknown>,
+    errorName?: string
+  ) => {
+    try {
+      await attempt;
+    } catch (err) {
+      if (errorName) {
+        assert.include(err.toString(), errorName);
+      }
+      return;
+    }
+    assert.fail("transaction should have been rejected");
+  };
+
+  let seed: Uint8Array;
+
+  before(async () => {
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        authority.publicKey,
+        10 * LAMPORTS_PER_SOL
+      ),
+      "confirmed"
+    );
+
+    const mint = await createMint(
+      provider.connection,
+      authority,
+      authority.publicKey,
+      null,
+      6
+    );
+
+    await program.methods
+      .initializeRegistry()
+      .accountsPartial({ registry, authority: authority.publicKey })
+      .signers([authority])
+      .rpc();
+
+    const prizeTiers = Array(11).fill(0);
+    prizeTiers[6] = 10_000;
+    await program.methods
+      .initialize({
+        sequenceLength: 6,
+        revealInterval: new anchor.BN(1),
+        ticketPrice: new anchor.BN(1_000_000),
+        prizeTiers,
+        seedCommitments: Array.from({ length: 10 }, () => Array(32).fill(0)),
+        settlementPeriod: new anchor.BN(60),
+        salesCloseIndex: 6,
+        priceMultipliers: Array(10).fill(10_000),
+        uniqueSequences: false,
+        minNumber: 1,
+        maxNumber: 49,
+        allowRepeats: false,
+        matchMode: { orderedExact: {} },
+      })
+      .accountsPartial({
+        registry,
+        lottery,
+        registryEntry,
+        mint,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([authority])
+      .rpc();
+
+    await program.methods
+      .configureOracle(oracleProgram.programId, oracleSigner.publicKey)
+      .accountsPartial({ lottery, authority: authority.publicKey, proposal: null })
+      .signers([authority])
+      .rpc();
+
+    await program.methods
+      .requestReveal()
+      .accountsPartial({
+        lottery,
+        revealRequest,
+        authority: authority.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+
+    seed = Uint8Array.from(
+      (await program.account.revealRequest.fetch(revealRequest)).seed
+    );
+  });
+
+  it("rejects a proof signed by another key", async () => {
+    const { ix, proof } = signSeed(Keypair.generate(), seed);
+    await expectRejection(
+      fulfill(proof).preInstructions([ix]).rpc(),
+      "InvalidOracleProof"
+    );
+  });
+
+  it("rejects a signature over another message", async () => {
+    const { ix, proof } = signSeed(oracleSigner, new Uint8Array(32).fill(1));
+    await expectRejection(
+      fulfill(proof).preInstructions([ix]).rpc(),
+      "InvalidOracleProof"
+    );
+  });
+
+  it("rejects a fulfillment without the ed25519 instruction", async () => {
+    const { proof } = signSeed(oracleSigner, seed);
+    await expectRejection(fulfill(proof).rpc());
+  });
+
+  it("rejects fulfill_reveal signed by anyone but the oracle program", async () => {
+    const impostor = Keypair.generate();
+    const { ix, proof } = signSeed(oracleSigner, seed);
+    await expectRejection(
+      program.methods
+        .fulfillReveal(proof)
+        .accountsPartial({
+          lottery,
+          revealRequest,
+          oracleAuthority: impostor.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .preInstructions([ix])
+        .signers([impostor])
+        .rpc(),
+      "UnauthorizedOracle"
+    );
+  });
+
+  it("reveals the first number from the oracle's proof", async () => {
+    const { ix, proof } = signSeed(oracleSigner, seed);
+    await fulfill(proof).preInstructions([ix]).rpc();
+
+    const request = await program.account.revealRequest.fetch(revealRequest);
+    assert.isTrue(request.fulfilled);
+
+    const state = await program.account.lottery.fetch(lottery);
+    assert.equal(state.currentRevealIndex, 1);
+    assert.isAtLeast(state.winningSequence[0], 1);
+    assert.isAtMost(state.winningSequence[0], 49);
+  });
+
+  it("rejects a second fulfillment of the same request", async () => {
+    const { ix, proof } = signSeed(oracleSigner, seed);
+    await expectRejection(fulfill(proof).preInstructions([ix]).rpc());
+  });
+});
@@ -1,16 +1,90 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
+import { PublicKey } from "@solana/web3.js";
+import { createMint, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
 import { Sol } from "../target/types/sol";
 
 describe("sol", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
 
   const program = anchor.workspace.sol as Program<Sol>;
+  const authority = provider.wallet.publicKey;
+
+  const [registry] = PublicKey.findProgramAddressSync(
+    [Buffer.from("registry"), authority.toBuffer()],
+    program.programId
+  );
+  const [registryEntry] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("entry"),
+      registry.toBuffer(),
+      new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+  const [lottery] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("lottery"),
+      authority.toBuffer(),
+      new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
 
   it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+    const mint = await createMint(
+      provider.connection,
+      (provider.wallet as anchor.Wallet).payer,
+      authority,
+      null,
+      6
+    );
+
+    await program.methods
+      .initializeRegistry()
+      .accountsPartial({ registry, authority })
+      .rpc();
+
+    const prizeTiers = Array(11).fill(0);
+    prizeTiers[5] = 2_000;
+    prizeTiers[6] = 8_000;
+    await program.methods
+      .initialize({
+        sequenceLength: 6,
+        revealInterval: new anchor.BN(60),
+        ticketPrice: new anchor.BN(1_000_000),
+        prizeTiers,
+        seedCommitments: Array.from({ length: 10 }, () => Array(32).fill(0)),
+        settlementPeriod: new anchor.BN(60),
+        salesCloseIndex: 3,
+        priceMultipliers: Array(10).fill(10_000),
+        uniqueSequences: true,
+        minNumber: 1,
+        maxNumber: 49,
+        allowRepeats: false,
+        matchMode: { orderedExact: {} },
+      })
+      .accountsPartial({
+        registry,
+        lottery,
+        registryEntry,
+        mint,
+        authority,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const state = await program.account.lottery.fetch(lottery);
+    assert.isTrue(state.authority.equals(authority));
+    assert.isTrue(state.mint.equals(mint));
+    assert.equal(state.sequenceLength, 6);
+    assert.deepEqual(state.state, { active: {} });
+
+    const entry = await program.account.registryEntry.fetch(registryEntry);
+    assert.isTrue(entry.lottery.equals(lottery));
+    assert.isTrue(entry.authority.equals(authority));
   });
 });
@@ -0,0 +1,127 @@
+//! The rules both lottery programs score tickets by.
+//!
+//! Everything that decides whether two tickets are the same or how well one
+//! did against the draw goes through here: prize matching, the duplicate
+//! sequence key and offers on revealed prefixes. The programs each keep their
+//! own `MatchMode` for their IDLs and convert it into a [`Rule`].
+
+use anchor_lang::solana_program::hash::{hash, Hash};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// Same number in the same position
+    OrderedExact,
+    /// Distinct picked numbers drawn anywhere, classic lotto
+    UnorderedSet,
+    /// Positions matched from the first until the first miss
+    OrderedPrefix,
+}
+
+/// How many of the `picked` numbers match `drawn`, position for position
+/// where the rule is ordered.
+pub fn count_matches(picked: &[u8], drawn: &[u8], rule: Rule) -> usize {
+    match rule {
+        Rule::OrderedExact => picked
+            .iter()
+            .zip(drawn)
+            .filter(|(picked, drawn)| picked == drawn)
+            .count(),
+        Rule::UnorderedSet => picked
+            .iter()
+            .enumerate()
+            .filter(|(i, number)| !picked[..*i].contains(number) && drawn.contains(number))
+            .count(),
+        Rule::OrderedPrefix => picked
+            .iter()
+            .zip(drawn)
+            .take_while(|(picked, drawn)| picked == drawn)
+            .count(),
+    }
+}
+
+/// Whether `picked` matches every number `revealed` so far: anywhere in the
+/// ticket for a set, otherwise as its leading numbers.
+pub fn matches_revealed(picked: &[u8], revealed: &[u8], rule: Rule) -> bool {
+    match rule {
+        Rule::UnorderedSet => revealed.iter().all(|number| picked.contains(number)),
+        Rule::OrderedExact | Rule::OrderedPrefix => picked.starts_with(revealed),
+    }
+}
+
+/// The hash a sequence claim PDA is seeded with, equal for any two tickets
+/// that always score the same.
+///
+/// Clients derive it as the sha256 of the picked numbers as bytes, in the
+/// order picked. Under [`Rule::UnorderedSet`] order and repeats don't affect
+/// the score, so the hash is of the distinct numbers in ascending order
+/// instead: `[9, 3, 3, 5]` hashes as `[3, 5, 9]`.
+pub fn sequence_key(picked: &[u8], rule: Rule) -> Hash {
+    match rule {
+        Rule::UnorderedSet => {
+            let mut distinct = picked.to_vec();
+            distinct.sort_unstable();
+            distinct.dedup();
+            hash(&distinct)
+        }
+        Rule::OrderedExact | Rule::OrderedPrefix => hash(picked),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWN: [u8; 6] = [1, 2, 3, 4, 5, 6];
+
+    #[test]
+    fn ordered_exact_counts_matching_positions() {
+        assert_eq!(count_matches(&[1, 9, 3, 8, 5, 7], &DRAWN, Rule::OrderedExact), 3);
+        assert_eq!(count_matches(&DRAWN, &DRAWN, Rule::OrderedExact), 6);
+    }
+
+    #[test]
+    fn unordered_set_ignores_positions_and_repeated_picks() {
+        assert_eq!(count_matches(&[6, 5, 4, 3, 2, 1], &DRAWN, Rule::UnorderedSet), 6);
+        assert_eq!(count_matches(&[1, 1, 1, 9, 9, 2], &DRAWN, Rule::UnorderedSet), 2);
+    }
+
+    #[test]
+    fn ordered_prefix_stops_at_first_miss() {
+        assert_eq!(count_matches(&[1, 2, 9, 4, 5, 6], &DRAWN, Rule::OrderedPrefix), 2);
+        assert_eq!(count_matches(&[9, 2, 3, 4, 5, 6], &DRAWN, Rule::OrderedPrefix), 0);
+    }
+
+    #[test]
+    fn revealed_numbers_match_as_a_prefix_or_a_subset() {
+        let picked = [1, 2, 3, 4, 5, 6];
+        for rule in [Rule::OrderedExact, Rule::OrderedPrefix] {
+            assert!(matches_revealed(&picked, &[1, 2], rule));
+            assert!(!matches_revealed(&picked, &[1, 2, 9], rule));
+            assert!(!matches_revealed(&picked, &[2, 1], rule));
+        }
+        assert!(matches_revealed(&picked, &[2, 1, 6], Rule::UnorderedSet));
+        assert!(!matches_revealed(&picked, &[2, 1, 9], Rule::UnorderedSet));
+        assert!(matches_revealed(&picked, &[], Rule::UnorderedSet));
+    }
+
+    #[test]
+    fn only_a_set_keys_permutations_alike() {
+        let picked = [9, 3, 5];
+        let permuted = [5, 9, 3];
+        assert_eq!(
+            sequence_key(&picked, Rule::UnorderedSet),
+            sequence_key(&permuted, Rule::UnorderedSet)
+        );
+        assert_eq!(sequence_key(&picked, Rule::UnorderedSet), hash(&[3, 5, 9]));
+        assert_eq!(
+            sequence_key(&[9, 3, 3, 5], Rule::UnorderedSet),
+            sequence_key(&picked, Rule::UnorderedSet)
+        );
+
+        // Ordered keys are the plain hash clients have always used
+        for rule in [Rule::OrderedExact, Rule::OrderedPrefix] {
+            assert_eq!(sequence_key(&picked, rule), hash(&picked));
+            assert_ne!(sequence_key(&picked, rule), sequence_key(&permuted, rule));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! The check both lottery programs run on an oracle's `fulfill_reveal` proof.
+//!
+//! The oracle's off-chain side signs the reveal request's seed and puts an
+//! ed25519 verify instruction right before the fulfillment. The runtime checks
+//! the signature itself; all that's left for the program is making sure that
+//! instruction verified the right signer, message and signature.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+// Deprecated in favour of solana-instructions-sysvar, but saves a direct dependency
+#[allow(deprecated)]
+use anchor_lang::solana_program::sysvar::instructions::get_instruction_relative;
+
+/// Whether the instruction before the current one is an ed25519 verify of
+/// `proof` as `signer`'s signature over `seed`.
+pub fn proof_verified(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    seed: &[u8; 32],
+    proof: &[u8; 64],
+) -> bool {
+    let Ok(ix) = get_instruction_relative(-1, instructions) else {
+        return false;
+    };
+    if ix.program_id != ed25519_program::ID {
+        return false;
+    }
+
+    // Layout: signature count, padding, then one 14-byte offsets record
+    let data = &ix.data;
+    if data.len() < 16 || data[0] != 1 {
+        return false;
+    }
+    let read = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+
+    // Everything must be embedded in the ed25519 instruction itself
+    if read(4) != u16::MAX || read(8) != u16::MAX || read(14) != u16::MAX {
+        return false;
+    }
+
+    let field = |offset: u16, len: usize| data.get(offset as usize..offset as usize + len);
+    field(read(6), 32) == Some(signer.as_ref())
+        && field(read(2), 64) == Some(&proof[..])
+        && read(12) == 32
+        && field(read(10), 32) == Some(&seed[..])
+}
+
+#[cfg(test)]
+#[allow(deprecated)] // Instructions sysvar helpers, as above
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::instruction::Instruction;
+    use anchor_lang::solana_program::sysvar::instructions::{
+        self as instructions_sysvar, construct_instructions_data, store_current_index,
+        BorrowedAccountMeta, BorrowedInstruction,
+    };
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer as _};
+    use solana_ed25519_program::new_ed25519_instruction_with_signature;
+
+    const SEED: [u8; 32] = [7; 32];
+
+    fn oracle_keypair(byte: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[byte; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    fn oracle_key(keypair: &Keypair) -> Pubkey {
+        Pubkey::new_from_array(keypair.public.to_bytes())
+    }
+
+    // The ed25519 verify instruction a client puts before fulfill, and the proof
+    fn sign(keypair: &Keypair, message: &[u8]) -> (Instruction, [u8; 64]) {
+        let signature = keypair.sign(message).to_bytes();
+        let ix = new_ed25519_instruction_with_signature(
+            message,
+            &signature,
+            &keypair.public.to_bytes(),
+        );
+        (ix, signature)
+    }
+
+    // Runs proof_verified as if from an instruction placed after `preceding`
+    fn verified_after(preceding: &[Instruction], signer: &Pubkey, proof: &[u8; 64]) -> bool {
+        let fulfill = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data: vec![],
+        };
+        let borrowed: Vec<BorrowedInstruction> = preceding
+            .iter()
+            .chain([&fulfill])
+            .map(|ix| BorrowedInstruction {
+                program_id: &ix.program_id,
+                accounts: ix
+                    .accounts
+                    .iter()
+                    .map(|meta| BorrowedAccountMeta {
+                        pubkey: &meta.pubkey,
+                        is_signer: meta.is_signer,
+                        is_writable: meta.is_writable,
+                    })
+                    .collect(),
+                data: &ix.data,
+            })
+            .collect();
+
+        let mut data = construct_instructions_data(&borrowed);
+        store_current_index(&mut data, preceding.len() as u16);
+        let mut lamports = 0;
+        let owner = Pubkey::default();
+        let key = instructions_sysvar::ID;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+
+        proof_verified(&info, signer, &SEED, proof)
+    }
+
+    #[test]
+    fn accepts_proof_from_oracle_signer() {
+        let oracle = oracle_keypair(1);
+        let (ix, proof) = sign(&oracle, &SEED);
+        assert!(verified_after(&[ix], &oracle_key(&oracle), &proof));
+    }
+
+    #[test]
+    fn rejects_proof_from_another_signer() {
+        let oracle = oracle_keypair(1);
+        let (ix, proof) = sign(&oracle_keypair(2), &SEED);
+        assert!(!verified_after(&[ix], &oracle_key(&oracle), &proof));
+    }
+
+    #[test]
+    fn rejects_signature_over_another_message() {
+        let oracle = oracle_keypair(1);
+        let (ix, proof) = sign(&oracle, &[8; 32]);
+        assert!(!verified_after(&[ix], &oracle_key(&oracle), &proof));
+    }
+
+    #[test]
+    fn rejects_proof_other_than_the_verified_signature() {
+        let oracle = oracle_keypair(1);
+        let (ix, _) = sign(&oracle, &SEED);
+        let (_, other_proof) = sign(&oracle, &[8; 32]);
+        assert!(!verified_after(&[ix], &oracle_key(&oracle), &other_proof));
+    }
+
+    #[test]
+    fn rejects_missing_ed25519_instruction() {
+        let oracle = oracle_keypair(1);
+        let (_, proof) = sign(&oracle, &SEED);
+        assert!(!verified_after(&[], &oracle_key(&oracle), &proof));
+
+        let other = Instruction {
+            program_id: Pubkey::default(),
+            accounts: vec![],
+            data: vec![],
+        };
+        assert!(!verified_after(&[other], &oracle_key(&oracle), &proof));
+    }
+
+    #[test]
+    fn rejects_inputs_read_from_other_instructions() {
+        let oracle = oracle_keypair(1);
+        let (mut ix, proof) = sign(&oracle, &SEED);
+        // Point the message at instruction 0 instead of the ed25519 data itself
+        ix.data[14..16].copy_from_slice(&0u16.to_le_bytes());
+        assert!(!verified_after(&[ix], &oracle_key(&oracle), &proof));
+    }
+}
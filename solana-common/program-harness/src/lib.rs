@@ -0,0 +1,405 @@
+//! Instruction-level tests for the lottery programs without a validator.
+//!
+//! There's no SBF toolchain or solana-program-test for Solana 2.x to build
+//! against offline, so `Bank` does what program-test's builtin mode does: it
+//! calls each Anchor program's `entry` natively, with account memory laid out
+//! like the loader's, and answers its syscalls and CPIs in process. The system
+//! program is emulated; SPL Token and Token-2022 run their real processors.
+//!
+//! ```ignore
+//! let mut bank = Bank::new();
+//! bank.add_program(my_program::ID, my_program::entry);
+//! bank.process(&[instruction], &[payer])?;
+//! ```
+
+mod runtime;
+mod system;
+mod token;
+
+pub use token::TransferFee;
+
+#[allow(deprecated)] // Instructions sysvar helpers; see the programs' imports
+use anchor_lang::solana_program::sysvar::instructions::{
+    construct_instructions_data, store_current_index, BorrowedAccountMeta, BorrowedInstruction,
+};
+use anchor_lang::solana_program::account_info::AccountInfo;
+use anchor_lang::solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::rent::Rent;
+use anchor_lang::solana_program::slot_hashes::SlotHashes;
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
+use ed25519_dalek::Signer as _;
+use runtime::{AccountMemory, Runtime};
+use std::collections::HashMap;
+
+/// A program's entrypoint, as Anchor's `entry` and the SPL processors have it.
+pub type Entrypoint = for<'info> fn(&Pubkey, &'info [AccountInfo<'info>], &[u8]) -> ProgramResult;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Account {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+}
+
+pub struct Bank {
+    accounts: HashMap<Pubkey, Account>,
+    programs: HashMap<Pubkey, Entrypoint>,
+    clock: Clock,
+    rent: Rent,
+    events: Vec<Vec<u8>>,
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bank {
+    pub fn new() -> Self {
+        let mut bank = Self {
+            accounts: HashMap::new(),
+            programs: HashMap::new(),
+            clock: Clock {
+                slot: 1,
+                unix_timestamp: 1_700_000_000,
+                ..Clock::default()
+            },
+            rent: Rent::default(),
+            events: vec![],
+        };
+        bank.add_builtin(system_program::ID, system::process);
+        bank.add_builtin(spl_token::ID, spl_token::processor::Processor::process);
+        bank.add_builtin(spl_token_2022::ID, spl_token_2022::processor::Processor::process);
+        bank.set_account(ed25519_program::ID, executable(Pubkey::default(), vec![]));
+        bank
+    }
+
+    fn add_builtin(&mut self, program_id: Pubkey, entrypoint: Entrypoint) {
+        self.programs.insert(program_id, entrypoint);
+        self.set_account(program_id, executable(Pubkey::default(), vec![]));
+    }
+
+    /// Deploys `entrypoint` at `program_id` as an upgradeable program with no
+    /// upgrade authority.
+    pub fn add_program(&mut self, program_id: Pubkey, entrypoint: Entrypoint) {
+        let programdata_address = programdata_address(&program_id);
+        let program = UpgradeableLoaderState::Program {
+            programdata_address,
+        };
+        self.programs.insert(program_id, entrypoint);
+        self.set_account(
+            program_id,
+            executable(bpf_loader_upgradeable::ID, bincode::serialize(&program).unwrap()),
+        );
+        self.set_upgrade_authority(&program_id, None);
+    }
+
+    pub fn set_upgrade_authority(&mut self, program_id: &Pubkey, authority: Option<Pubkey>) {
+        let programdata = UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: authority,
+        };
+        let data = bincode::serialize(&programdata).unwrap();
+        let account = Account {
+            lamports: self.rent.minimum_balance(data.len()),
+            data,
+            owner: bpf_loader_upgradeable::ID,
+            executable: false,
+        };
+        self.set_account(programdata_address(program_id), account);
+    }
+
+    pub fn set_account(&mut self, key: Pubkey, account: Account) {
+        self.accounts.insert(key, account);
+    }
+
+    pub fn account(&self, key: &Pubkey) -> Option<&Account> {
+        self.accounts.get(key)
+    }
+
+    pub fn lamports(&self, key: &Pubkey) -> u64 {
+        self.accounts.get(key).map_or(0, |account| account.lamports)
+    }
+
+    /// Funds a system account, creating it if needed.
+    pub fn airdrop(&mut self, key: &Pubkey, lamports: u64) {
+        self.accounts.entry(*key).or_default().lamports += lamports;
+    }
+
+    /// A funded system account to sign with.
+    pub fn new_user(&mut self) -> Pubkey {
+        let user = Pubkey::new_unique();
+        self.airdrop(&user, 100_000_000_000);
+        user
+    }
+
+    /// Deserializes an Anchor account; panics if it's missing or invalid.
+    pub fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+        let account = self
+            .accounts
+            .get(key)
+            .unwrap_or_else(|| panic!("no account {key}"));
+        T::try_deserialize(&mut account.data.as_slice()).expect("invalid account data")
+    }
+
+    pub fn rent(&self) -> &Rent {
+        &self.rent
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Moves time forward to `unix_timestamp`, a slot later.
+    pub fn warp_to(&mut self, unix_timestamp: i64) {
+        self.clock.slot += 1;
+        self.clock.unix_timestamp = unix_timestamp;
+    }
+
+    /// Processes `instructions` atomically as one transaction signed by
+    /// `signers`, like the runtime would.
+    pub fn process(&mut self, instructions: &[Instruction], signers: &[Pubkey]) -> ProgramResult {
+        self.refresh_sysvars();
+        let before = self.accounts.clone();
+
+        runtime::enter(Runtime::new(
+            self.programs.clone(),
+            self.clock.clone(),
+            self.rent.clone(),
+        ));
+        let result = self.execute(instructions, signers, &before);
+        let runtime = runtime::exit();
+
+        match result {
+            Ok(()) => {
+                // Accounts left without lamports are gone once the transaction lands
+                self.accounts.retain(|_, account| account.lamports > 0);
+                self.events = runtime.events;
+                Ok(())
+            }
+            Err(error) => {
+                self.accounts = before;
+                Err(error)
+            }
+        }
+    }
+
+    /// The events of type `T` the last successful transaction emitted.
+    pub fn events<T: AnchorDeserialize + Discriminator>(&self) -> Vec<T> {
+        self.events
+            .iter()
+            .filter_map(|data| data.strip_prefix(T::DISCRIMINATOR))
+            .map(|mut data| T::deserialize(&mut data).expect("invalid event data"))
+            .collect()
+    }
+
+    fn refresh_sysvars(&mut self) {
+        let hash = hashv(&[b"slot", &self.clock.slot.to_le_bytes()]);
+        let slot_hashes = SlotHashes::new(&[(self.clock.slot, hash)]);
+        self.set_sysvar(sysvar::clock::ID, bincode::serialize(&self.clock).unwrap());
+        self.set_sysvar(sysvar::rent::ID, bincode::serialize(&self.rent).unwrap());
+        self.set_sysvar(sysvar::slot_hashes::ID, bincode::serialize(&slot_hashes).unwrap());
+    }
+
+    fn set_sysvar(&mut self, key: Pubkey, data: Vec<u8>) {
+        let account = Account {
+            lamports: self.rent.minimum_balance(data.len()).max(1),
+            data,
+            owner: sysvar::ID,
+            executable: false,
+        };
+        self.set_account(key, account);
+    }
+
+    fn execute(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[Pubkey],
+        before: &HashMap<Pubkey, Account>,
+    ) -> ProgramResult {
+        let datas: Vec<&[u8]> = instructions.iter().map(|ix| ix.data.as_slice()).collect();
+        for (index, instruction) in instructions.iter().enumerate() {
+            self.set_sysvar(sysvar::instructions::ID, instructions_data(instructions, index));
+            let result = if instruction.program_id == ed25519_program::ID {
+                verify_ed25519(&instruction.data, &datas)
+            } else {
+                self.execute_instruction(instruction, signers)
+            };
+            if let Err(error) = result {
+                println!("Instruction {index} failed: {error}");
+                return Err(error);
+            }
+        }
+
+        // Whatever a transaction leaves with lamports has to be rent exempt
+        for (key, account) in &self.accounts {
+            if before.get(key) != Some(account)
+                && account.lamports > 0
+                && account.lamports < self.rent.minimum_balance(account.data.len())
+            {
+                println!("{key} isn't rent exempt");
+                return Err(ProgramError::AccountNotRentExempt);
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_instruction(&mut self, instruction: &Instruction, signers: &[Pubkey]) -> ProgramResult {
+        // One memory region per distinct account, with the privileges of all
+        // the places it's passed in
+        let mut keys: Vec<(Pubkey, bool, bool)> = vec![];
+        for meta in &instruction.accounts {
+            if meta.is_signer && !signers.contains(&meta.pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            match keys.iter_mut().find(|(key, ..)| *key == meta.pubkey) {
+                Some((_, is_signer, is_writable)) => {
+                    *is_signer |= meta.is_signer;
+                    *is_writable |= meta.is_writable;
+                }
+                None => keys.push((meta.pubkey, meta.is_signer, meta.is_writable)),
+            }
+        }
+        let mut memory: Vec<AccountMemory> = keys
+            .iter()
+            .map(|(key, ..)| AccountMemory::new(key, &self.accounts.get(key).cloned().unwrap_or_default()))
+            .collect();
+        let unique: Vec<AccountInfo> = memory
+            .iter_mut()
+            .zip(&keys)
+            // SAFETY: `memory` outlives every info made from it
+            .map(|(memory, (_, is_signer, is_writable))| unsafe { memory.info(*is_signer, *is_writable) })
+            .collect();
+        let accounts: Vec<AccountInfo> = instruction
+            .accounts
+            .iter()
+            .map(|meta| {
+                let position = keys.iter().position(|(key, ..)| *key == meta.pubkey).unwrap();
+                unique[position].clone()
+            })
+            .collect();
+
+        let total = |infos: &[AccountInfo]| infos.iter().map(|info| info.lamports() as u128).sum::<u128>();
+        let lamports_before = total(&unique);
+        runtime::invoke(&instruction.program_id, &accounts, &instruction.data)?;
+        if total(&unique) != lamports_before {
+            println!("{} created or destroyed lamports", instruction.program_id);
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        for info in unique.iter().filter(|info| info.is_writable) {
+            let account = Account {
+                lamports: info.lamports(),
+                data: info.data.borrow().to_vec(),
+                owner: *info.owner,
+                executable: info.executable,
+            };
+            self.accounts.insert(*info.key, account);
+        }
+        Ok(())
+    }
+}
+
+pub fn programdata_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+fn executable(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1,
+        data,
+        owner,
+        executable: true,
+    }
+}
+
+#[allow(deprecated)]
+fn instructions_data(instructions: &[Instruction], current: usize) -> Vec<u8> {
+    let borrowed: Vec<BorrowedInstruction> = instructions
+        .iter()
+        .map(|ix| BorrowedInstruction {
+            program_id: &ix.program_id,
+            accounts: ix
+                .accounts
+                .iter()
+                .map(|meta| BorrowedAccountMeta {
+                    pubkey: &meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: &ix.data,
+        })
+        .collect();
+    let mut data = construct_instructions_data(&borrowed);
+    store_current_index(&mut data, current as u16);
+    data
+}
+
+// What the ed25519 precompile checks: each offsets record names a signature,
+// key and message, in this instruction or another, and all must verify
+fn verify_ed25519(data: &[u8], datas: &[&[u8]]) -> ProgramResult {
+    let invalid = ProgramError::InvalidInstructionData;
+    let count = *data.first().ok_or(invalid.clone())? as usize;
+    let read = |at: usize| -> Result<usize, ProgramError> {
+        let bytes = data.get(at..at + 2).ok_or(ProgramError::InvalidInstructionData)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    };
+    let field = |index: usize, offset: usize, len: usize| -> Result<&[u8], ProgramError> {
+        let source = if index == u16::MAX as usize { data } else { datas.get(index).copied().unwrap_or(&[]) };
+        source.get(offset..offset + len).ok_or(ProgramError::InvalidInstructionData)
+    };
+
+    for record in 0..count {
+        let at = 2 + record * 14;
+        let signature = field(read(at + 2)?, read(at)?, 64)?;
+        let pubkey = field(read(at + 6)?, read(at + 4)?, 32)?;
+        let message = field(read(at + 12)?, read(at + 8)?, read(at + 10)?)?;
+
+        let pubkey = ed25519_dalek::PublicKey::from_bytes(pubkey).map_err(|_| invalid.clone())?;
+        let signature = ed25519_dalek::Signature::from_bytes(signature).map_err(|_| invalid.clone())?;
+        pubkey
+            .verify_strict(message, &signature)
+            .map_err(|_| invalid.clone())?;
+    }
+    Ok(())
+}
+
+/// An oracle's signing key, made from a fixed secret so tests are repeatable.
+pub struct Ed25519Signer(ed25519_dalek::Keypair);
+
+impl Ed25519Signer {
+    pub fn from_seed(byte: u8) -> Self {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[byte; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        Self(ed25519_dalek::Keypair { secret, public })
+    }
+
+    pub fn pubkey(&self) -> Pubkey {
+        Pubkey::new_from_array(self.0.public.to_bytes())
+    }
+
+    /// The signature over `message`, and the ed25519 verify instruction for it
+    /// that goes right before whatever checks the proof.
+    pub fn sign(&self, message: &[u8]) -> ([u8; 64], Instruction) {
+        let signature = self.0.sign(message).to_bytes();
+        let instruction = solana_ed25519_program::new_ed25519_instruction_with_signature(
+            message,
+            &signature,
+            &self.0.public.to_bytes(),
+        );
+        (signature, instruction)
+    }
+}
@@ -0,0 +1,342 @@
+//! The in-process stand-in for the SBF runtime: account memory laid out the way
+//! the loader serializes it, the syscall stubs programs reach natively, and CPI
+//! with the runtime's signer, writability and ownership rules.
+
+use crate::{Account, Entrypoint};
+use anchor_lang::solana_program::account_info::AccountInfo;
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE};
+use anchor_lang::solana_program::epoch_schedule::EpochSchedule;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::msg;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::rent::Rent;
+use anchor_lang::solana_program::sysvar;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Once;
+
+// Offsets into an account's serialized input. AccountInfo::realloc and
+// original_data_len read and write around the key and data natively too.
+const ORIGINAL_DATA_LEN: usize = 4;
+const KEY: usize = 8;
+const OWNER: usize = 40;
+const LAMPORTS: usize = 72;
+const DATA_LEN: usize = 80;
+const DATA: usize = 88;
+
+/// One account's memory for the length of an instruction, with room to grow.
+pub(crate) struct AccountMemory {
+    words: Vec<u64>, // u64s keep lamports and the length fields aligned
+    executable: bool,
+}
+
+impl AccountMemory {
+    pub(crate) fn new(key: &Pubkey, account: &Account) -> Self {
+        let len = account.data.len();
+        let size = DATA + len + MAX_PERMITTED_DATA_INCREASE + 8;
+        let mut words = vec![0u64; size.div_ceil(8)];
+
+        let bytes = Self::bytes(&mut words);
+        bytes[ORIGINAL_DATA_LEN..KEY].copy_from_slice(&(len as u32).to_le_bytes());
+        bytes[KEY..OWNER].copy_from_slice(key.as_ref());
+        bytes[OWNER..LAMPORTS].copy_from_slice(account.owner.as_ref());
+        bytes[LAMPORTS..DATA_LEN].copy_from_slice(&account.lamports.to_le_bytes());
+        bytes[DATA_LEN..DATA].copy_from_slice(&(len as u64).to_le_bytes());
+        bytes[DATA..DATA + len].copy_from_slice(&account.data);
+
+        Self {
+            words,
+            executable: account.executable,
+        }
+    }
+
+    fn bytes(words: &mut [u64]) -> &mut [u8] {
+        let len = words.len() * 8;
+        // SAFETY: u8 has no alignment requirement and the length is in bytes
+        unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, len) }
+    }
+
+    /// # Safety
+    ///
+    /// The info points into this memory, which must outlive it.
+    pub(crate) unsafe fn info<'a>(&mut self, is_signer: bool, is_writable: bool) -> AccountInfo<'a> {
+        let base = self.words.as_mut_ptr() as *mut u8;
+        let len = *(base.add(DATA_LEN) as *const u64) as usize;
+        AccountInfo::new(
+            &*(base.add(KEY) as *const Pubkey),
+            is_signer,
+            is_writable,
+            &mut *(base.add(LAMPORTS) as *mut u64),
+            std::slice::from_raw_parts_mut(base.add(DATA), len),
+            &*(base.add(OWNER) as *const Pubkey),
+            self.executable,
+            u64::MAX,
+        )
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct Snapshot {
+    lamports: u64,
+    owner: Pubkey,
+    data: Vec<u8>,
+}
+
+impl Snapshot {
+    fn of(info: &AccountInfo) -> Self {
+        Self {
+            lamports: info.lamports(),
+            owner: *info.owner,
+            data: info.data.borrow().to_vec(),
+        }
+    }
+}
+
+// A program running, and its accounts as they were when it last had control
+struct Frame {
+    program_id: Pubkey,
+    before: HashMap<Pubkey, Snapshot>,
+}
+
+/// What a transaction's programs can see of the bank while it's processed.
+pub(crate) struct Runtime {
+    pub programs: HashMap<Pubkey, Entrypoint>,
+    pub clock: Clock,
+    pub rent: Rent,
+    pub events: Vec<Vec<u8>>,
+    frames: Vec<Frame>,
+    return_data: Option<(Pubkey, Vec<u8>)>,
+}
+
+impl Runtime {
+    pub fn new(programs: HashMap<Pubkey, Entrypoint>, clock: Clock, rent: Rent) -> Self {
+        Self {
+            programs,
+            clock,
+            rent,
+            events: vec![],
+            frames: vec![],
+            return_data: None,
+        }
+    }
+}
+
+thread_local! {
+    // Tests run on their own threads, each with its own bank
+    static RUNTIME: RefCell<Option<Runtime>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn enter(runtime: Runtime) {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(Stubs));
+    });
+    RUNTIME.with(|current| *current.borrow_mut() = Some(runtime));
+}
+
+pub(crate) fn exit() -> Runtime {
+    RUNTIME.with(|current| current.borrow_mut().take().expect("no transaction to finish"))
+}
+
+fn with_runtime<R>(f: impl FnOnce(&mut Runtime) -> R) -> R {
+    RUNTIME.with(|current| {
+        let mut current = current.borrow_mut();
+        f(current.as_mut().expect("syscall outside of a transaction"))
+    })
+}
+
+/// Runs `program_id` over `accounts`, then holds it to the runtime's rules on
+/// what it may change.
+pub(crate) fn invoke(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let entrypoint = with_runtime(|runtime| runtime.programs.get(program_id).copied())
+        .ok_or(ProgramError::IncorrectProgramId)?;
+    let frame = Frame {
+        program_id: *program_id,
+        before: accounts
+            .iter()
+            .map(|info| (*info.key, Snapshot::of(info)))
+            .collect(),
+    };
+    with_runtime(|runtime| runtime.frames.push(frame));
+
+    // SAFETY: the program can't hold on to its accounts past the call
+    let static_accounts: &'static [AccountInfo<'static>] = unsafe { std::mem::transmute(accounts) };
+    let result = entrypoint(program_id, static_accounts, data);
+
+    let frame = with_runtime(|runtime| runtime.frames.pop().expect("frame pushed above"));
+    result?;
+    check_changes(&frame, accounts)
+}
+
+// Read-only and executable accounts can't change at all. Only the owner can
+// debit an account, write its data, or hand it to another owner, and only once
+// its data is cleared.
+fn check_changes(frame: &Frame, accounts: &[AccountInfo]) -> ProgramResult {
+    for info in accounts {
+        let Some(before) = frame.before.get(info.key) else {
+            continue;
+        };
+        let after = Snapshot::of(info);
+        if after == *before {
+            continue;
+        }
+
+        let is_writable = accounts
+            .iter()
+            .any(|other| other.key == info.key && other.is_writable);
+        let owned = before.owner == frame.program_id;
+        let (violation, error) = if info.executable || !is_writable {
+            ("modified a read-only account", ProgramError::Immutable)
+        } else if after.lamports < before.lamports && !owned {
+            ("debited an account it doesn't own", ProgramError::IllegalOwner)
+        } else if after.data != before.data && !owned {
+            ("wrote to an account it doesn't own", ProgramError::IllegalOwner)
+        } else if after.owner != before.owner && !(owned && after.data.iter().all(|b| *b == 0)) {
+            ("reassigned an account it can't", ProgramError::IllegalOwner)
+        } else {
+            continue;
+        };
+        msg!("{} {} {}", frame.program_id, violation, info.key);
+        return Err(error);
+    }
+    Ok(())
+}
+
+struct Stubs;
+
+impl SyscallStubs for Stubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = with_runtime(|runtime| runtime.frames.last().map(|frame| frame.program_id))
+            .expect("CPI from outside a program");
+        let signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+
+        let mut accounts = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if meta.is_writable && !info.is_writable {
+                msg!("{} passed read-only {} as writable", caller, meta.pubkey);
+                return Err(ProgramError::Immutable);
+            }
+            if meta.is_signer && !info.is_signer && !signers.contains(&meta.pubkey) {
+                msg!("{} can't sign for {}", caller, meta.pubkey);
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            let mut info = info.clone();
+            info.is_signer = meta.is_signer;
+            info.is_writable = meta.is_writable;
+            accounts.push(info);
+        }
+
+        // The caller answers for its own changes up to the call
+        let caller_frame = with_runtime(|runtime| {
+            let frame = runtime.frames.last().expect("caller frame");
+            Frame {
+                program_id: frame.program_id,
+                before: frame.before.clone(),
+            }
+        });
+        check_changes(&caller_frame, &accounts)?;
+
+        invoke(&instruction.program_id, &accounts, &instruction.data)?;
+
+        // And from here on, the callee's changes are the caller's starting point
+        with_runtime(|runtime| {
+            let frame = runtime.frames.last_mut().expect("caller frame");
+            for info in &accounts {
+                frame.before.insert(*info.key, Snapshot::of(info));
+            }
+        });
+        Ok(())
+    }
+
+    fn sol_get_sysvar(
+        &self,
+        sysvar_id_addr: *const u8,
+        var_addr: *mut u8,
+        offset: u64,
+        length: u64,
+    ) -> u64 {
+        // SAFETY: the caller passes a pointer to a sysvar id
+        let id = unsafe { *(sysvar_id_addr as *const Pubkey) };
+        let bytes = with_runtime(|runtime| {
+            if id == sysvar::clock::ID {
+                bincode::serialize(&runtime.clock).ok()
+            } else if id == sysvar::rent::ID {
+                bincode::serialize(&runtime.rent).ok()
+            } else {
+                None
+            }
+        });
+        let Some(bytes) = bytes else {
+            return u64::from(ProgramError::UnsupportedSysvar);
+        };
+        let (offset, length) = (offset as usize, length as usize);
+        let Some(slice) = bytes.get(offset..offset + length) else {
+            return u64::from(ProgramError::InvalidArgument);
+        };
+        // SAFETY: the caller passes a buffer of `length` bytes
+        unsafe { std::ptr::copy_nonoverlapping(slice.as_ptr(), var_addr, length) };
+        0
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = with_runtime(|runtime| runtime.clock.clone());
+        // SAFETY: the caller passes a pointer to a Clock
+        unsafe { std::ptr::write(var_addr as *mut Clock, clock) };
+        0
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let rent = with_runtime(|runtime| runtime.rent.clone());
+        // SAFETY: the caller passes a pointer to a Rent
+        unsafe { std::ptr::write(var_addr as *mut Rent, rent) };
+        0
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // SAFETY: the caller passes a pointer to an EpochSchedule
+        unsafe { std::ptr::write(var_addr as *mut EpochSchedule, EpochSchedule::default()) };
+        0
+    }
+
+    fn sol_remaining_compute_units(&self) -> u64 {
+        1_400_000
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        with_runtime(|runtime| runtime.return_data.clone())
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        with_runtime(|runtime| {
+            let program_id = runtime.frames.last().map(|frame| frame.program_id);
+            runtime.return_data = match (program_id, data.is_empty()) {
+                (Some(program_id), false) => Some((program_id, data.to_vec())),
+                _ => None,
+            };
+        });
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        with_runtime(|runtime| runtime.events.push(fields.concat()));
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        with_runtime(|runtime| runtime.frames.len() as u64)
+    }
+}
@@ -0,0 +1,79 @@
+//! The system program instructions Anchor's `init` and lamport transfers use.
+
+// The system instruction types moved to solana-system-interface, which would
+// be one more direct dependency
+#![allow(deprecated)]
+
+use anchor_lang::solana_program::account_info::AccountInfo;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::system_instruction::{SystemError, SystemInstruction};
+use anchor_lang::solana_program::system_program;
+
+pub(crate) fn process(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let instruction: SystemInstruction =
+        bincode::deserialize(data).map_err(|_| ProgramError::InvalidInstructionData)?;
+    let account = |index: usize| accounts.get(index).ok_or(ProgramError::NotEnoughAccountKeys);
+
+    match instruction {
+        SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        } => {
+            let (from, to) = (account(0)?, account(1)?);
+            if to.lamports() > 0 {
+                return Err(system_error(SystemError::AccountAlreadyInUse));
+            }
+            transfer(from, to, lamports)?;
+            allocate(to, space)?;
+            assign(to, &owner)
+        }
+        SystemInstruction::Transfer { lamports } => transfer(account(0)?, account(1)?, lamports),
+        SystemInstruction::Assign { owner } => assign(account(0)?, &owner),
+        SystemInstruction::Allocate { space } => allocate(account(0)?, space),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn system_error(error: SystemError) -> ProgramError {
+    ProgramError::Custom(error as u32)
+}
+
+fn transfer(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !from.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !from.data_is_empty() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let remaining = from
+        .lamports()
+        .checked_sub(lamports)
+        .ok_or(system_error(SystemError::ResultWithNegativeLamports))?;
+    **from.try_borrow_mut_lamports()? = remaining;
+    **to.try_borrow_mut_lamports()? += lamports;
+    Ok(())
+}
+
+fn allocate(account: &AccountInfo, space: u64) -> ProgramResult {
+    if !account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !account.data_is_empty() || *account.owner != system_program::ID {
+        return Err(system_error(SystemError::AccountAlreadyInUse));
+    }
+    account.realloc(space as usize, true)
+}
+
+fn assign(account: &AccountInfo, owner: &Pubkey) -> ProgramResult {
+    if account.owner == owner {
+        return Ok(());
+    }
+    if !account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    account.assign(owner);
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Mints and token accounts to test with, under SPL Token or Token-2022.
+
+use crate::{Account, Bank};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::{
+    extension::{
+        transfer_fee::{instruction::initialize_transfer_fee_config, TransferFeeAmount, TransferFeeConfig},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    instruction::{initialize_account3, initialize_mint2, mint_to},
+    state::{Account as TokenAccount, Mint},
+};
+
+/// A Token-2022 transfer fee: basis points of each transfer, capped at
+/// `maximum`.
+#[derive(Clone, Copy)]
+pub struct TransferFee {
+    pub basis_points: u16,
+    pub maximum: u64,
+}
+
+impl Bank {
+    /// A mint under `token_program` that `authority` mints, with a transfer
+    /// fee if given (Token-2022 only).
+    pub fn create_mint(
+        &mut self,
+        token_program: &Pubkey,
+        authority: &Pubkey,
+        decimals: u8,
+        transfer_fee: Option<TransferFee>,
+    ) -> Pubkey {
+        let mint = Pubkey::new_unique();
+        let len = match transfer_fee {
+            Some(_) => ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig])
+                .unwrap(),
+            None if *token_program == spl_token::ID => spl_token::state::Mint::LEN,
+            None => ExtensionType::try_calculate_account_len::<Mint>(&[]).unwrap(),
+        };
+        self.create_token_owned(&mint, token_program, len);
+
+        let mut instructions = vec![];
+        if let Some(fee) = transfer_fee {
+            instructions.push(
+                initialize_transfer_fee_config(
+                    token_program,
+                    &mint,
+                    Some(authority),
+                    Some(authority),
+                    fee.basis_points,
+                    fee.maximum,
+                )
+                .unwrap(),
+            );
+        }
+        instructions.push(initialize_mint2(token_program, &mint, authority, None, decimals).unwrap());
+        self.process(&instructions, &[]).expect("mint initializes");
+        mint
+    }
+
+    /// A token account for `mint` held by `owner`, with whatever extensions
+    /// the mint requires.
+    pub fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let mint_account = self.account(mint).expect("mint exists").clone();
+        let token_program = mint_account.owner;
+        let len = if token_program == spl_token::ID {
+            spl_token::state::Account::LEN
+        } else {
+            let mint_state = StateWithExtensions::<Mint>::unpack(&mint_account.data).unwrap();
+            let extensions = ExtensionType::get_required_init_account_extensions(
+                &mint_state.get_extension_types().unwrap(),
+            );
+            ExtensionType::try_calculate_account_len::<TokenAccount>(&extensions).unwrap()
+        };
+
+        let account = Pubkey::new_unique();
+        self.create_token_owned(&account, &token_program, len);
+        let instruction = initialize_account3(&token_program, &account, mint, owner).unwrap();
+        self.process(&[instruction], &[]).expect("token account initializes");
+        account
+    }
+
+    fn create_token_owned(&mut self, key: &Pubkey, token_program: &Pubkey, len: usize) {
+        let account = Account {
+            lamports: self.rent().minimum_balance(len),
+            data: vec![0; len],
+            owner: *token_program,
+            executable: false,
+        };
+        self.set_account(*key, account);
+    }
+
+    pub fn mint_to(&mut self, mint: &Pubkey, account: &Pubkey, authority: &Pubkey, amount: u64) {
+        let token_program = self.account(mint).expect("mint exists").owner;
+        let instruction = mint_to(&token_program, mint, account, authority, &[], amount).unwrap();
+        self.process(&[instruction], &[*authority]).expect("tokens mint");
+    }
+
+    /// A token account's balance, zero if it's been closed.
+    pub fn token_balance(&self, account: &Pubkey) -> u64 {
+        self.account(account).map_or(0, |account| {
+            StateWithExtensions::<TokenAccount>::unpack(&account.data)
+                .unwrap()
+                .base
+                .amount
+        })
+    }
+
+    /// Transfer fees withheld in a Token-2022 token account.
+    pub fn withheld_in_account(&self, account: &Pubkey) -> u64 {
+        let account = self.account(account).expect("token account exists");
+        let state = StateWithExtensions::<TokenAccount>::unpack(&account.data).unwrap();
+        state
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fees| fees.withheld_amount.into())
+    }
+
+    /// Transfer fees harvested to a Token-2022 mint.
+    pub fn withheld_in_mint(&self, mint: &Pubkey) -> u64 {
+        let account = self.account(mint).expect("mint exists");
+        let state = StateWithExtensions::<Mint>::unpack(&account.data).unwrap();
+        state
+            .get_extension::<TransferFeeConfig>()
+            .map_or(0, |config| config.withheld_amount.into())
+    }
+}